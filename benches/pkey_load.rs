@@ -0,0 +1,66 @@
+//! Latency of a batch worker loading a proving key from its circuit data, read from the
+//! file or through a memory map.
+//!
+//! ```text
+//! cargo bench --bench pkey_load -- 22
+//! ```
+//!
+//! Each round loads the key into a fresh cache, as a new batch worker does. The file stays
+//! in the page cache between rounds, drop it (`echo 3 > /proc/sys/vm/drop_caches`) before
+//! a run to measure a cold disk.
+use circuits_batcher::proof::ParamsCache;
+use circuits_batcher::proof::ProvingKeyCache;
+use circuits_batcher::report::Report;
+use circuits_batcher::samples::simple::SimpleCircuit;
+use halo2_proofs::pairing::bn256::Bn256;
+use halo2_proofs::pairing::bn256::Fr;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+const ROUNDS: usize = 5;
+
+fn main() {
+    // cargo bench passes --bench before our args
+    let k = std::env::args()
+        .skip(1)
+        .find_map(|x| x.parse::<usize>().ok())
+        .unwrap_or(18);
+    let params_dir = PathBuf::from("params");
+    std::fs::create_dir_all(&params_dir).unwrap();
+
+    let report = Report::default();
+    let params_cache = ParamsCache::<Bn256>::new(1, params_dir.clone());
+    let params = params_cache.generate_k_params(k, &report).unwrap();
+    let circuit = SimpleCircuit::<Fr> {
+        a: Fr::from(100u64),
+        b: Fr::from(200u64),
+    };
+    let name = format!("bench_simple_k{}.circuit.data", k);
+    // keygen writes the circuit data on the first run
+    ProvingKeyCache::<Bn256>::new(1, params_dir.clone())
+        .load_or_build_pkey(&circuit, &params, name.clone(), &report)
+        .unwrap();
+
+    for mmap in [false, true] {
+        let mut samples = vec![];
+        for _ in 0..ROUNDS {
+            let mut pkey_cache = ProvingKeyCache::<Bn256>::new(1, params_dir.clone());
+            pkey_cache.mmap = mmap;
+            let start = Instant::now();
+            pkey_cache
+                .load_pkey(&params, name.clone(), &report)
+                .unwrap();
+            samples.push(start.elapsed());
+        }
+        samples.sort();
+        println!(
+            "k {} {}: min {:?}, median {:?}, mean {:?}",
+            k,
+            if mmap { "mmap" } else { "read" },
+            samples[0],
+            samples[ROUNDS / 2],
+            samples.iter().sum::<Duration>() / ROUNDS as u32
+        );
+    }
+}
@@ -1,24 +1,37 @@
-use crate::args::HashType;
+use crate::args::Curve;
+use crate::batch::BatchConfig;
 use crate::batch::CommitmentCheck;
-use crate::exec::exec_batch_proofs;
+use crate::batcher::Batcher;
+use crate::error::Error;
+use crate::error::Result;
+use crate::exec::exec_check;
+use crate::exec::exec_export_vkey;
+use crate::exec::exec_params_downsize;
+use crate::exec::exec_params_import;
+use crate::exec::exec_params_info;
+use crate::exec::exec_params_list;
+use crate::exec::exec_params_verify;
+use crate::exec::exec_prove;
 use crate::exec::exec_solidity_gen;
-use crate::proof::load_or_build_unsafe_params;
+use crate::exec::exec_verify;
+use crate::plan::BatchPlan;
 use crate::proof::ParamsCache;
 use crate::proof::ProofGenerationInfo;
-use crate::proof::ProofInfo;
 use crate::proof::ProvingKeyCache;
-use ark_std::end_timer;
-use ark_std::start_timer;
+use crate::report::Report;
 use clap::App;
 use clap::AppSettings;
+use clap::ArgMatches;
+use halo2_proofs::arithmetic::MultiMillerLoop;
+use halo2_proofs::arithmetic::MultiMillerLoopOnProvePairing;
 use halo2_proofs::pairing::bn256::Bn256;
-use halo2_proofs::poly::commitment::ParamsVerifier;
+use halo2aggregator_s::circuit_verifier::G2AffineBaseHelper;
+use halo2aggregator_s::circuit_verifier::GtHelper;
 use halo2aggregator_s::circuits::utils::TranscriptHash;
-use halo2aggregator_s::native_verifier;
 use log::debug;
+use log::warn;
 use std::fs;
 use std::path::PathBuf;
-use std::sync::Mutex;
 /*
 use log::info;
 */
@@ -36,11 +49,20 @@ pub trait AppBuilder: CommandBuilder {
             .version(Self::VERSION)
             .setting(AppSettings::SubcommandRequired)
             .arg(Self::param_path_arg())
-            .arg(Self::output_path_arg());
+            .arg(Self::output_path_arg())
+            .arg(Self::curve_arg())
+            .arg(Self::require_trusted_params_arg())
+            .arg(Self::pkey_cache_bytes_arg())
+            .arg(Self::mmap_pkey_arg())
+            .arg(Self::report_arg());
 
         let app = Self::append_params_subcommand(app);
         let app = Self::append_setup_subcommand(app);
+        let app = Self::append_prove_subcommand(app);
         let app = Self::append_batch_subcommand(app);
+        let app = Self::append_plan_subcommand(app);
+        let app = Self::append_check_subcommand(app);
+        let app = Self::append_export_vkey_subcommand(app);
         let app = Self::append_verify_subcommand(app);
         let app = Self::append_generate_solidity_verifier(app);
         app
@@ -49,8 +71,49 @@ pub trait AppBuilder: CommandBuilder {
     fn exec(command: App) {
         env_logger::init();
 
+        if let Err(e) = Self::run(command) {
+            eprintln!("error: {}", e);
+            std::process::exit(e.exit_code());
+        }
+    }
+
+    fn run(command: App) -> Result<()> {
         let top_matches = command.get_matches();
+        let report_file = Self::parse_report_arg(&top_matches);
+        let report = match report_file {
+            Some(_) => {
+                // e.g. `params gen`
+                let mut command = vec![];
+                let mut matches = &top_matches;
+                while let Some((name, sub_matches)) = matches.subcommand() {
+                    command.push(name);
+                    matches = sub_matches;
+                }
+                Report::new(&command.join(" "))
+            }
+            None => Report::default(),
+        };
+
+        let result = match Self::parse_curve(&top_matches) {
+            Curve::Bn256 => Self::run_with_engine::<Bn256>(&top_matches, &report),
+        };
+
+        match report_file {
+            // the error of the run takes precedence over failing to write its report
+            Some(report_file) => {
+                let written = report.finish(&report_file, &result);
+                result.and(written)
+            }
+            None => result,
+        }
+    }
 
+    fn run_with_engine<
+        E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+    >(
+        top_matches: &ArgMatches,
+        report: &Report,
+    ) -> Result<()> {
         let output_dir = top_matches
             .get_one::<PathBuf>("output")
             .expect("output dir is not provided");
@@ -59,149 +122,294 @@ pub trait AppBuilder: CommandBuilder {
             .get_one::<PathBuf>("params")
             .expect("params dir is not provided");
 
-        let params_cache = Mutex::new(ParamsCache::new(5, params_dir.clone()));
-        let pkey_cache =
-            Mutex::<ProvingKeyCache<Bn256>>::new(ProvingKeyCache::new(5, params_dir.clone()));
+        let mut params_cache = ParamsCache::<E>::new(5, params_dir.clone());
+        params_cache.require_trusted_params = Self::parse_require_trusted_params_arg(top_matches);
+        let mut pkey_cache = match Self::parse_pkey_cache_bytes_arg(top_matches)? {
+            Some(max_bytes) => ProvingKeyCache::<E>::with_max_bytes(max_bytes, params_dir.clone()),
+            None => ProvingKeyCache::<E>::new(5, params_dir.clone()),
+        };
+        pkey_cache.mmap = Self::parse_mmap_pkey_arg(top_matches);
 
-        fs::create_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).map_err(Error::io(output_dir))?;
         println!("output dir: {:?}", output_dir);
 
-        fs::create_dir_all(&params_dir).unwrap();
+        fs::create_dir_all(&params_dir).map_err(Error::io(params_dir))?;
         println!("params dir: {:?}", params_dir);
 
+        report.setting("params_dir", params_dir.display());
+        report.setting("output_dir", output_dir.display());
+
+        let result = Self::run_subcommand(
+            top_matches,
+            params_dir,
+            output_dir,
+            &params_cache,
+            &pkey_cache,
+            report,
+        );
+
+        println!("params cache: {}", params_cache.stats());
+        println!("pkey cache: {}", pkey_cache.stats());
+        report.cache("params", params_cache.stats());
+        report.cache("pkey", pkey_cache.stats());
+        result
+    }
+
+    /// Runs the subcommand, its errors are returned after the cache stats of the run are
+    /// printed by `run_with_engine`.
+    fn run_subcommand<
+        E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+    >(
+        top_matches: &ArgMatches,
+        params_dir: &PathBuf,
+        output_dir: &PathBuf,
+        params_cache: &ParamsCache<E>,
+        pkey_cache: &ProvingKeyCache<E>,
+        report: &Report,
+    ) -> Result<()> {
         match top_matches.subcommand() {
             Some(("setup", sub_matches)) => {
                 let k: u32 = Self::parse_zkwasm_k_arg(&sub_matches).unwrap();
-                generate_k_params(k, &output_dir, params_cache.lock().as_mut().unwrap());
+                match sub_matches.get_one::<PathBuf>("output") {
+                    Some(setup_dir) => {
+                        warn!(
+                            "setup --output is deprecated, params belong in the params dir (--params)"
+                        );
+                        fs::create_dir_all(setup_dir).map_err(Error::io(setup_dir))?;
+                        generate_k_params(k, setup_dir, params_cache, report)
+                    }
+                    None => generate_k_params(k, &params_dir, params_cache, report),
+                }
+            }
+
+            Some(("params", sub_matches)) => match sub_matches.subcommand() {
+                Some(("gen", gen_matches)) => {
+                    let k = Self::parse_zkwasm_k_arg(gen_matches).unwrap();
+                    generate_k_params(k, &params_dir, params_cache, report)
+                }
+                Some(("info", info_matches)) => {
+                    let k = Self::parse_zkwasm_k_arg(info_matches).unwrap();
+                    exec_params_info::<E>(k, params_dir, report)
+                }
+                Some(("list", _)) => exec_params_list::<E>(params_dir, report),
+                Some(("import", import_matches)) => {
+                    let k = Self::parse_zkwasm_k_arg(import_matches).unwrap();
+                    let ptau = Self::parse_ptau_arg(import_matches);
+                    let params_file = exec_params_import(&ptau, k, params_dir, report)?;
+                    println!("params file: {:?}", params_file);
+                    Ok(())
+                }
+                Some(("downsize", downsize_matches)) => {
+                    let k = Self::parse_zkwasm_k_arg(downsize_matches).unwrap();
+                    let from = Self::parse_from_k_arg(downsize_matches);
+                    let params_file =
+                        exec_params_downsize(from, k, params_dir, params_cache, report)?;
+                    println!("params file: {:?}", params_file);
+                    Ok(())
+                }
+                Some(("verify", _)) => {
+                    let problems = exec_params_verify(params_dir)?;
+                    if problems > 0 {
+                        return Err(Error::InvalidArgument(format!(
+                            "{} files of the params dir failed the check",
+                            problems
+                        )));
+                    }
+                    println!("params dir is valid");
+                    Ok(())
+                }
+                _ => unreachable!(),
+            },
+
+            Some(("prove", sub_matches)) => {
+                let open_schema = Self::parse_openschema(&sub_matches);
+                let config_files = Self::parse_proof_load_info_arg(sub_matches);
+                let loadinfos = exec_prove(
+                    params_cache,
+                    pkey_cache,
+                    output_dir,
+                    &config_files,
+                    open_schema,
+                    report,
+                )?;
+                println!("proof load info: {:?}", loadinfos);
+                Ok(())
             }
 
             Some(("batch", sub_matches)) => {
-                let k: u32 = Self::parse_zkwasm_k_arg(&sub_matches).unwrap();
+                let k = Self::parse_zkwasm_k_arg(&sub_matches);
+                let auto_k = Self::parse_auto_k_arg(sub_matches);
                 let hash = Self::parse_hashtype(&sub_matches);
                 let open_schema = Self::parse_openschema(&sub_matches);
                 let accumulator = Self::parse_accumulator(&sub_matches);
                 let config_files = Self::parse_proof_load_info_arg(sub_matches);
                 let batch_script_files = Self::parse_commits_equiv_info_arg(sub_matches);
                 let cont = Self::parse_cont_arg(sub_matches);
+                let resume = Self::parse_resume_arg(sub_matches);
+                let extend = Self::parse_extend_arg(sub_matches);
+                let finalize = Self::parse_finalize_arg(sub_matches);
+                let tree_node = Self::parse_tree_node_arg(sub_matches);
+                let mock = Self::parse_mock_arg(sub_matches);
+                let batch_config = match Self::parse_batch_config_arg(sub_matches) {
+                    Some(config_file) => BatchConfig::load(&config_file, report)?,
+                    None => BatchConfig::default(),
+                };
+                let tree = Self::parse_tree_arg(sub_matches) || tree_node.is_some();
                 let proof_name = sub_matches
                     .get_one::<String>("name")
                     .expect("name of the prove task is not provided");
 
                 let batch_script_info = batch_script_files
                     .into_iter()
-                    .map(|x| CommitmentCheck::load(x.as_path()))
-                    .collect::<Vec<_>>();
+                    .map(|x| CommitmentCheck::load(x.as_path(), report))
+                    .collect::<Result<Vec<_>>>()?;
                 debug!("commits equivalent {:?}", batch_script_info);
-                exec_batch_proofs(
-                    params_cache.lock().as_mut().unwrap(),
-                    pkey_cache.lock().as_mut().unwrap(),
-                    proof_name,
-                    output_dir,
-                    params_dir,
-                    config_files,
-                    batch_script_info,
-                    hash,
-                    k,
-                    cont,
-                    true,
-                    open_schema,
-                    accumulator,
+                let mut batcher = Batcher::new(
+                    params_dir.clone(),
+                    output_dir.clone(),
+                    params_cache,
+                    pkey_cache,
                 )
+                .auto_k(auto_k)
+                .hash(hash)
+                .open_schema(open_schema)
+                .accumulator(accumulator)
+                .config(batch_config)
+                .mock(mock)
+                .continuation(cont)
+                .resume(resume)
+                .extend(extend)
+                .finalize(finalize)
+                .tree(tree)
+                .tree_node(tree_node)
+                .report(report.clone());
+                if let Some(k) = k {
+                    batcher = batcher.k(k);
+                }
+                let outcome = batcher.batch(proof_name, &config_files, batch_script_info)?;
+                println!("batch proof load info: {:?}", outcome.loadinfo);
+                Ok(())
             }
 
-            Some(("verify", sub_matches)) => {
-                let config_files = Self::parse_proof_load_info_arg(&sub_matches);
-                let hash = Self::parse_hashtype(&sub_matches);
-                for config_file in config_files.iter() {
-                    let proofloadinfo = ProofGenerationInfo::load(config_file);
-                    let proofs: Vec<ProofInfo<Bn256>> =
-                        ProofInfo::load_proof(&output_dir, &params_dir, &proofloadinfo);
-                    let mut param_cache_lock = params_cache.lock(); //This is tricky. Cannot put this directly in the load_or_build_unsafe_params. Have to do this.
-                    let params = load_or_build_unsafe_params::<Bn256>(
-                        proofloadinfo.k,
-                        &params_dir.join(format!("K{}.params", proofloadinfo.k)),
-                        param_cache_lock.as_mut().unwrap(),
-                    );
-                    let mut public_inputs_size = 0;
-                    for proof in proofs.iter() {
-                        public_inputs_size = usize::max(
-                            public_inputs_size,
-                            proof
-                                .instances
-                                .iter()
-                                .fold(0, |acc, x| usize::max(acc, x.len())),
-                        );
+            Some(("export-vkey", sub_matches)) => {
+                let config_files = Self::parse_proof_load_info_arg(sub_matches);
+                let loadinfos =
+                    exec_export_vkey::<E>(params_dir, output_dir, &config_files, report)?;
+                println!("proof load info: {:?}", loadinfos);
+                Ok(())
+            }
+
+            Some(("check", sub_matches)) => {
+                let config_files = Self::parse_proof_load_info_arg(sub_matches);
+                let commits = Self::parse_commits_equiv_info_arg(sub_matches);
+                let problems = exec_check::<E>(params_dir, &config_files, &commits, report)?;
+                if problems > 0 {
+                    return Err(Error::InvalidArgument(format!(
+                        "{} problems found in the commits sheets",
+                        problems
+                    )));
+                }
+                println!("commits sheets are valid");
+                Ok(())
+            }
+
+            Some(("plan", sub_matches)) => match sub_matches.subcommand() {
+                Some(("run", run_matches)) => {
+                    let plan = BatchPlan::load(&Self::parse_plan_arg(run_matches), report)?;
+                    let outputs =
+                        plan.run(params_dir, output_dir, params_cache, pkey_cache, report)?;
+                    for (name, loadinfo) in outputs {
+                        println!("batch step {} load info: {:?}", name, loadinfo);
                     }
+                    Ok(())
+                }
+                _ => unreachable!(),
+            },
 
-                    let params_verifier: ParamsVerifier<Bn256> =
-                        params.verifier(public_inputs_size).unwrap();
-                    let timer = start_timer!(|| "native verify single proof");
-                    for (_, proof) in proofs.iter().enumerate() {
-                        native_verifier::verify_single_proof::<Bn256>(
-                            &params_verifier,
-                            &proof.vkey,
-                            &proof.instances,
-                            proof.transcripts.clone(),
-                            match hash {
-                                HashType::Poseidon => TranscriptHash::Poseidon,
-                                HashType::Sha => TranscriptHash::Sha,
-                                HashType::Keccak => TranscriptHash::Keccak,
-                            },
-                            true,
-                            &vec![],
-                        );
+            Some(("verify", sub_matches)) => {
+                let config_files = Self::parse_proof_load_info_arg(&sub_matches);
+                let hash = Self::parse_optional_hashtype(&sub_matches);
+                let results = exec_verify::<E>(
+                    params_dir,
+                    output_dir,
+                    &config_files,
+                    hash,
+                    params_cache,
+                    report,
+                );
+                for result in results.iter() {
+                    let proof = result
+                        .proof_idx
+                        .map_or("load info".to_string(), |i| format!("proof {}", i));
+                    match &result.error {
+                        None => println!(
+                            "{:?} {}: ok in {:.3}s",
+                            result.loadinfo, proof, result.seconds
+                        ),
+                        Some(e) => println!(
+                            "{:?} {}: failed in {:.3}s, {}",
+                            result.loadinfo, proof, result.seconds, e
+                        ),
                     }
-                    end_timer!(timer);
                 }
+                let failed = results.iter().filter(|x| !x.passed()).count();
+                println!("verified {} proofs, {} failed", results.len(), failed);
+                if failed > 0 {
+                    return Err(Error::VerificationFailed(format!(
+                        "{} of {} proofs failed",
+                        failed,
+                        results.len()
+                    )));
+                }
+                Ok(())
             }
 
             Some(("solidity", sub_matches)) => {
-                let k: u32 = Self::parse_zkwasm_k_arg(&sub_matches).unwrap();
                 let config_file = Self::parse_proof_load_info_arg(sub_matches);
                 let n_proofs = config_file.len() - 1;
                 let sol_path: PathBuf = Self::parse_sol_dir_arg(&sub_matches);
-                let hash = Self::parse_hashtype(&sub_matches);
-                let hasher = match hash {
-                    HashType::Poseidon => TranscriptHash::Poseidon,
-                    HashType::Sha => TranscriptHash::Sha,
-                    HashType::Keccak => TranscriptHash::Keccak,
-                };
+                let proofloadinfo = ProofGenerationInfo::load(&config_file[0], report)?;
+                let (hash, k) = proofloadinfo.check_recorded(
+                    Self::parse_optional_hashtype(&sub_matches),
+                    Self::parse_zkwasm_k_arg(&sub_matches).map(|k| k as usize),
+                )?;
+                proofloadinfo.check_digests(&params_dir)?;
+                let k = k as u32;
+                let hasher = hash.transcript_hash();
                 let mut sol_path_templates: PathBuf = sol_path.clone();
                 sol_path_templates.push("templates");
                 let mut sol_path_contracts: PathBuf = sol_path.clone();
                 sol_path_contracts.push("contracts");
-                let proofloadinfo = ProofGenerationInfo::load(&config_file[0]);
 
                 match hasher {
-                    TranscriptHash::Keccak => {
-                        exec_solidity_gen::<sha3::Keccak256>(
-                            params_dir,
-                            output_dir,
-                            k,
-                            n_proofs,
-                            &sol_path_templates,
-                            &sol_path_contracts,
-                            &proofloadinfo,
-                            params_cache.lock().as_mut().unwrap(),
-                            hasher,
-                        );
-                    }
-                    TranscriptHash::Sha => {
-                        exec_solidity_gen::<sha2::Sha256>(
-                            params_dir,
-                            output_dir,
-                            k,
-                            n_proofs,
-                            &sol_path_templates,
-                            &sol_path_contracts,
-                            &proofloadinfo,
-                            params_cache.lock().as_mut().unwrap(),
-                            hasher,
-                        );
-                    }
-                    _ => {
-                        panic!("Solidity generation only supports Keccak and Sha hash functions");
-                    }
+                    TranscriptHash::Keccak => exec_solidity_gen::<E, sha3::Keccak256>(
+                        params_dir,
+                        output_dir,
+                        k,
+                        n_proofs,
+                        &sol_path_templates,
+                        &sol_path_contracts,
+                        &proofloadinfo,
+                        params_cache,
+                        hasher,
+                        report,
+                    ),
+                    TranscriptHash::Sha => exec_solidity_gen::<E, sha2::Sha256>(
+                        params_dir,
+                        output_dir,
+                        k,
+                        n_proofs,
+                        &sol_path_templates,
+                        &sol_path_contracts,
+                        &proofloadinfo,
+                        params_cache,
+                        hasher,
+                        report,
+                    ),
+                    _ => Err(Error::InvalidArgument(
+                        "Solidity generation only supports Keccak and Sha hash functions"
+                            .to_string(),
+                    )),
                 }
             }
             Some((_, _)) => todo!(),
@@ -7,6 +7,10 @@ use clap::ArgAction;
 use clap::ArgMatches;
 use serde::{Deserialize, Serialize};
 
+use crate::batcher::TreeNode;
+use crate::error::Error;
+use halo2aggregator_s::circuits::utils::TranscriptHash;
+
 #[derive(clap::ArgEnum, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum HashType {
     Poseidon,
@@ -14,6 +18,16 @@ pub enum HashType {
     Keccak,
 }
 
+impl HashType {
+    pub fn transcript_hash(&self) -> TranscriptHash {
+        match self {
+            HashType::Poseidon => TranscriptHash::Poseidon,
+            HashType::Sha => TranscriptHash::Sha,
+            HashType::Keccak => TranscriptHash::Keccak,
+        }
+    }
+}
+
 #[derive(clap::ArgEnum, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum OpenSchema {
     GWC,
@@ -26,6 +40,33 @@ pub enum Accumulator {
     UseHash,
 }
 
+/// Pairing engines provided by halo2_proofs that the aggregator circuit supports.
+#[derive(clap::ArgEnum, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Curve {
+    Bn256,
+}
+
+/// Parses a size in bytes with an optional K, M or G (binary) suffix.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "invalid size {}, expect bytes with an optional K, M or G",
+            s
+        )
+    };
+    let (digits, shift) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 10),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 20),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|x| x.checked_mul(1u64 << shift))
+        .ok_or_else(invalid)
+}
+
 pub trait ArgBuilder {
     fn hashtype<'a>() -> Arg<'a> {
         arg!(-c --challenge<CHALLENGE_HASH_TYPE>... "HashType of Challenge")
@@ -44,6 +85,15 @@ pub trait ArgBuilder {
             .value_parser(value_parser!(Accumulator))
     }
 
+    fn optional_hashtype<'a>() -> Arg<'a> {
+        arg!(-c --challenge [CHALLENGE_HASH_TYPE] "HashType of Challenge (default is the recorded one)")
+            .value_parser(value_parser!(HashType))
+    }
+
+    fn parse_optional_hashtype(matches: &ArgMatches) -> Option<HashType> {
+        matches.get_one::<HashType>("challenge").cloned()
+    }
+
     fn parse_hashtype(matches: &ArgMatches) -> HashType {
         matches
             .get_one::<HashType>("challenge")
@@ -58,6 +108,17 @@ pub trait ArgBuilder {
             .clone()
     }
 
+    fn curve_arg<'a>() -> Arg<'a> {
+        arg!(--curve [CURVE] "Pairing friendly curve of the proofs (default is bn256)")
+            .value_parser(value_parser!(Curve))
+    }
+
+    fn parse_curve(matches: &ArgMatches) -> Curve {
+        matches
+            .get_one::<Curve>("curve")
+            .map_or(Curve::Bn256, |x| x.clone())
+    }
+
     fn parse_accumulator(matches: &ArgMatches) -> Accumulator {
         matches
             .get_one::<Accumulator>("accumulator")
@@ -102,6 +163,188 @@ pub trait ArgBuilder {
         matches.get_one::<u32>("cont").map_or(None, |&x| Some(x))
     }
 
+    fn resume_arg<'a>() -> Arg<'a> {
+        arg!(
+            --resume "Resume continuation batching from the last checkpoint."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_resume_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("resume")
+    }
+
+    fn extend_arg<'a>() -> Arg<'a> {
+        arg!(
+            --extend "Append the segments to the existing rec chain of the task."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_extend_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("extend")
+    }
+
+    fn finalize_arg<'a>() -> Arg<'a> {
+        arg!(
+            --finalize "Generate the final proof when extending a rec chain."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_finalize_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("finalize")
+    }
+
+    fn auto_k_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"auto-k" "Select the minimal batch k, with -k as the upper bound."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_auto_k_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("auto-k")
+    }
+
+    fn batch_config_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"batch-config" [CONFIG] "Path of the aggregator config of the batch rounds."
+        )
+        .value_parser(value_parser!(PathBuf))
+    }
+
+    fn parse_batch_config_arg(matches: &ArgMatches) -> Option<PathBuf> {
+        matches.get_one::<PathBuf>("batch-config").cloned()
+    }
+
+    fn mock_arg<'a>() -> Arg<'a> {
+        arg!(
+            --mock "Check the aggregate circuit with the mock prover before proving."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_mock_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("mock")
+    }
+
+    fn tree_arg<'a>() -> Arg<'a> {
+        arg!(
+            --tree "Batch the segments as a binary tree instead of a rec chain."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_tree_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("tree")
+    }
+
+    fn tree_node_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"tree-node" [NODE] "Only prove the tree node <level>.<index> or final."
+        )
+        .value_parser(|s: &str| s.parse::<TreeNode>())
+    }
+
+    fn parse_tree_node_arg(matches: &ArgMatches) -> Option<TreeNode> {
+        matches.get_one::<TreeNode>("tree-node").cloned()
+    }
+
+    fn plan_arg<'a>() -> Arg<'a> {
+        arg!(
+            --plan <PLAN> "Path of the batch plan file."
+        )
+        .value_parser(value_parser!(PathBuf))
+    }
+
+    fn parse_plan_arg(matches: &ArgMatches) -> PathBuf {
+        matches
+            .get_one::<PathBuf>("plan")
+            .expect("plan file is required.")
+            .clone()
+    }
+
+    fn ptau_arg<'a>() -> Arg<'a> {
+        arg!(
+            --ptau <PTAU> "Path of the powers of tau ceremony file."
+        )
+        .value_parser(value_parser!(PathBuf))
+    }
+
+    fn parse_ptau_arg(matches: &ArgMatches) -> PathBuf {
+        matches
+            .get_one::<PathBuf>("ptau")
+            .expect("ptau file is required.")
+            .clone()
+    }
+
+    fn from_k_arg<'a>() -> Arg<'a> {
+        arg!(
+            --from <FROM_K> "K of the params to downsize."
+        )
+        .value_parser(value_parser!(u32))
+    }
+
+    fn parse_from_k_arg(matches: &ArgMatches) -> u32 {
+        *matches
+            .get_one::<u32>("from")
+            .expect("k of the params to downsize is required.")
+    }
+
+    fn pkey_cache_bytes_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"pkey-cache-bytes" [BYTES] "Bound of the proving key cache in bytes, e.g. 64G. Defaults to $PKEY_CACHE_BYTES or 5 keys."
+        )
+        .value_parser(parse_byte_size)
+    }
+
+    fn parse_pkey_cache_bytes_arg(matches: &ArgMatches) -> crate::error::Result<Option<u64>> {
+        match matches.get_one::<u64>("pkey-cache-bytes") {
+            Some(bytes) => Ok(Some(*bytes)),
+            None => std::env::var("PKEY_CACHE_BYTES")
+                .ok()
+                .map(|x| {
+                    parse_byte_size(&x)
+                        .map_err(|e| Error::InvalidArgument(format!("PKEY_CACHE_BYTES: {}", e)))
+                })
+                .transpose(),
+        }
+    }
+
+    fn mmap_pkey_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"mmap-pkey" "Read the circuit data of proving keys through a memory map."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_mmap_pkey_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("mmap-pkey")
+    }
+
+    fn require_trusted_params_arg<'a>() -> Arg<'a> {
+        arg!(
+            --"require-trusted-params" "Fail on missing params instead of generating them by unsafe setup, and on params not from a ceremony."
+        )
+        .takes_value(false)
+    }
+
+    fn parse_require_trusted_params_arg(matches: &ArgMatches) -> bool {
+        matches.contains_id("require-trusted-params")
+    }
+
+    fn report_arg<'a>() -> Arg<'a> {
+        arg!(
+            --report [REPORT] "Path of the json report of the run."
+        )
+        .value_parser(value_parser!(PathBuf))
+    }
+
+    fn parse_report_arg(matches: &ArgMatches) -> Option<PathBuf> {
+        matches.get_one::<PathBuf>("report").cloned()
+    }
+
     fn commits_info_arg<'a>() -> Arg<'a> {
         Arg::new("commits")
             .long("commits")
@@ -180,3 +423,15 @@ pub trait ArgBuilder {
             .map_or(false, |_| true)
     }
 }
+
+#[test]
+fn parse_byte_size_test() {
+    assert_eq!(parse_byte_size("1024"), Ok(1024));
+    assert_eq!(parse_byte_size("4k"), Ok(4 << 10));
+    assert_eq!(parse_byte_size("4K"), Ok(4 << 10));
+    assert_eq!(parse_byte_size("64M"), Ok(64 << 20));
+    assert_eq!(parse_byte_size("64G"), Ok(64 << 30));
+    for invalid in ["", "G", "1.5G", "-1", "64T", "64 G", "99999999999G"] {
+        assert!(parse_byte_size(invalid).is_err(), "{}", invalid);
+    }
+}
@@ -1,24 +1,82 @@
 use crate::args::HashType;
 use crate::args::OpenSchema;
+use crate::error::Error;
+use crate::error::Result;
 use crate::proof::ParamsCache;
 use crate::proof::ProofGenerationInfo;
 use crate::proof::ProofInfo;
 use crate::proof::ProofPieceInfo;
 use crate::proof::ProvingKeyCache;
+use crate::report::Report;
+use crate::rows::count_rows;
+use crate::utils::closest_name;
 use ark_std::end_timer;
 use ark_std::start_timer;
+use halo2_proofs::arithmetic::CurveAffine;
 use halo2_proofs::arithmetic::MultiMillerLoop;
 use halo2_proofs::arithmetic::{Engine, MultiMillerLoopOnProvePairing};
+use halo2_proofs::dev::MockProver;
+use halo2_proofs::plonk::VerifyingKey;
 use halo2_proofs::poly::commitment::{Params, ParamsVerifier};
 use halo2aggregator_s::circuit_verifier::build_aggregate_verify_circuit;
 use halo2aggregator_s::circuit_verifier::circuit::AggregatorCircuit;
 use halo2aggregator_s::circuit_verifier::G2AffineBaseHelper;
 use halo2aggregator_s::circuit_verifier::GtHelper;
-use halo2aggregator_s::circuits::utils::{AggregatorConfig, TranscriptHash};
+use halo2aggregator_s::circuits::utils::AggregatorConfig;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
 
+/// Aggregate circuit with its instances, shadow instances and the hash of its instances,
+/// as returned by `build_aggregate_circuit`.
+pub type AggregateCircuit<E> = (
+    AggregatorCircuit<E>,
+    Vec<<E as Engine>::Scalar>,
+    Vec<<E as Engine>::Scalar>,
+    <E as Engine>::Scalar,
+);
+
+/// Knobs of the aggregator circuit of a batch round, see `AggregatorConfig`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct AggregatorOptions {
+    /// Challenge hash of the target proofs verified in the circuit.
+    pub transcript_hash: HashType,
+    pub use_select_chip: bool,
+    /// Rows of the aggregate circuit reserved for the pairing check.
+    pub circuit_rows_for_pairing: usize,
+}
+
+impl Default for AggregatorOptions {
+    fn default() -> Self {
+        AggregatorOptions {
+            transcript_hash: HashType::Poseidon,
+            use_select_chip: true,
+            circuit_rows_for_pairing: 500000,
+        }
+    }
+}
+
+/// Aggregator knobs of the rounds of a batch. Intermediate rounds are the continuation
+/// rounds and tree nodes whose proofs are batched again, their proofs use the
+/// intermediate transcript hash.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct BatchConfig {
+    #[serde(default)]
+    pub intermediate: AggregatorOptions,
+    #[serde(default, rename = "final")]
+    pub final_round: AggregatorOptions,
+}
+
+impl BatchConfig {
+    pub fn load(config_file: &Path, report: &Report) -> Result<Self> {
+        let fd = std::fs::File::open(config_file).map_err(Error::io(config_file))?;
+        println!("read batch config {:?}", config_file);
+        report.input(config_file);
+        serde_json::from_reader(fd).map_err(Error::parse(config_file))
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CommitmentName {
     pub name: String,
@@ -30,6 +88,8 @@ pub struct CommitmentName {
 pub struct CommitmentInInstance {
     pub name: String,
     pub proof_idx: usize,
+    #[serde(default)]
+    pub column: usize, // instance column, the first one for older sheets
     pub group_idx: usize, // instances are grouped by 3 as commits
 }
 
@@ -52,17 +112,148 @@ pub struct CommitmentCheck {
     pub absorb: Vec<CommitmentAbsorb>,
 }
 
+/// A problem found by `CommitmentCheck::validate`.
+#[derive(Debug)]
+pub struct CommitmentIssue {
+    /// Entry of the sheet, e.g. `equivalents[0].source`.
+    pub entry: String,
+    pub error: Error,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for CommitmentIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.entry, self.error)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Locates the proof of `name`/`proof_idx` among the groups of `proofsinfo`, returning
+/// its flattened index and the piece info.
+fn locate_proof<'a>(
+    proofsinfo: &'a Vec<ProofGenerationInfo>,
+    name: &String,
+    proof_idx: usize,
+) -> std::result::Result<(usize, &'a ProofPieceInfo), (Error, Option<String>)> {
+    let mut idx = 0;
+    for proofinfo in proofsinfo.iter() {
+        if &proofinfo.name == name {
+            return proofinfo
+                .proofs
+                .get(proof_idx)
+                .map(|piece| (idx + proof_idx, piece))
+                .ok_or_else(|| {
+                    let error = Error::ProofIndexOutOfRange {
+                        name: name.clone(),
+                        proof_idx,
+                        size: proofinfo.proofs.len(),
+                    };
+                    (error, None)
+                });
+        }
+        idx += proofinfo.proofs.len()
+    }
+    let suggestion = closest_name(name, proofsinfo.iter().map(|x| x.name.as_str()));
+    let error = Error::UnknownProofGroup {
+        name: name.clone(),
+        candidates: proofsinfo.iter().map(|x| x.name.clone()).collect(),
+    };
+    Err((error, suggestion.map(|x| x.to_string())))
+}
+
 impl CommitmentCheck {
-    pub fn load(equiv_file: &Path) -> Self {
-        let fd = std::fs::File::open(equiv_file)
-            .expect("Can not find batch config for commitment arith. [--commits]");
+    pub fn load(equiv_file: &Path, report: &Report) -> Result<Self> {
+        let fd = std::fs::File::open(equiv_file).map_err(Error::io(equiv_file))?;
         println!("read commit equivalents {:?}", equiv_file);
-        serde_json::from_reader(fd).unwrap()
+        report.input(equiv_file);
+        serde_json::from_reader(fd).map_err(Error::parse(equiv_file))
     }
 
-    pub fn save(&self, equiv_file: &Path) {
-        let fd = std::fs::File::create(equiv_file).unwrap();
-        serde_json::to_writer_pretty(fd, self).unwrap()
+    pub fn save(&self, equiv_file: &Path) -> Result<()> {
+        let fd = std::fs::File::create(equiv_file).map_err(Error::io(equiv_file))?;
+        serde_json::to_writer_pretty(fd, self).map_err(Error::serialize(equiv_file))
+    }
+
+    /// Resolves every entry of the sheet against the proof groups without building any
+    /// circuit. `vkeys` are the verifying keys of the proofs in the order the batcher
+    /// holds them. Returns all problems found.
+    pub fn validate<C: CurveAffine>(
+        &self,
+        proofsinfo: &Vec<ProofGenerationInfo>,
+        vkeys: &Vec<VerifyingKey<C>>,
+    ) -> Vec<CommitmentIssue> {
+        let mut issues = vec![];
+        let mut check_name = |entry: String, cn: &CommitmentName| {
+            let (error, suggestion) = match locate_proof(proofsinfo, &cn.name, cn.proof_idx) {
+                Ok((idx, _)) => {
+                    let named_advices = &vkeys[idx].cs.named_advices;
+                    if named_advices.iter().any(|r| r.0 == cn.column_name) {
+                        return;
+                    }
+                    let error = Error::UnknownCommitmentName {
+                        name: cn.name.clone(),
+                        proof_idx: cn.proof_idx,
+                        column_name: cn.column_name.clone(),
+                    };
+                    let suggestion =
+                        closest_name(&cn.column_name, named_advices.iter().map(|r| r.0.as_str()));
+                    (error, suggestion.map(|x| x.to_string()))
+                }
+                Err(e) => e,
+            };
+            issues.push(CommitmentIssue {
+                entry,
+                error,
+                suggestion,
+            });
+        };
+
+        for (i, eqs) in self.equivalents.iter().enumerate() {
+            check_name(format!("equivalents[{}].source", i), &eqs.source);
+            check_name(format!("equivalents[{}].target", i), &eqs.target);
+        }
+        for (i, exp) in self.expose.iter().enumerate() {
+            check_name(format!("expose[{}]", i), exp);
+        }
+        for (i, absorb) in self.absorb.iter().enumerate() {
+            check_name(format!("absorb[{}].target", i), &absorb.target);
+        }
+
+        for (i, absorb) in self.absorb.iter().enumerate() {
+            let ci = &absorb.instance_idx;
+            let error = match locate_proof(proofsinfo, &ci.name, ci.proof_idx) {
+                Ok((_, piece)) => {
+                    // the commitment takes the 3 instances after the first one
+                    let groups = piece
+                        .instance_size
+                        .get(ci.column)
+                        .map_or(0, |size| (*size as usize).saturating_sub(1) / 3);
+                    if ci.group_idx < groups {
+                        continue;
+                    }
+                    (
+                        Error::InstanceGroupOutOfRange {
+                            name: ci.name.clone(),
+                            proof_idx: ci.proof_idx,
+                            column: ci.column,
+                            group_idx: ci.group_idx,
+                            groups,
+                        },
+                        None,
+                    )
+                }
+                Err(e) => e,
+            };
+            issues.push(CommitmentIssue {
+                entry: format!("absorb[{}].instance_idx", i),
+                error: error.0,
+                suggestion: error.1,
+            });
+        }
+        issues
     }
 }
 
@@ -87,95 +278,91 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
         }
     }
 
+    /// Locates the flattened proof index of the `proof_idx`-th proof in group `name`.
+    fn get_proof_index(
+        &self,
+        proofsinfo: &Vec<ProofGenerationInfo>,
+        name: &String,
+        proof_idx: usize,
+    ) -> Result<usize> {
+        locate_proof(proofsinfo, name, proof_idx)
+            .map(|(idx, _)| idx)
+            .map_err(|(e, _)| e)
+    }
+
     pub fn get_commitment_index(
         &self,
         proofsinfo: &Vec<ProofGenerationInfo>,
         cn: &CommitmentName,
-    ) -> (usize, usize) {
-        let mut idx = 0;
-        let mut column_idx = None;
-        for proofinfo in proofsinfo.iter() {
-            if proofinfo.name == cn.name {
-                idx += cn.proof_idx;
-                let c = self.proofs[idx]
-                    .vkey
-                    .cs
-                    .named_advices
-                    .iter()
-                    .position(|r| r.0 == cn.column_name)
-                    .unwrap();
-                column_idx = Some(self.proofs[idx].vkey.cs.named_advices[c].1);
-                break;
-            } else {
-                idx += proofinfo.proofs.len()
-            }
-        }
-        if column_idx.is_none() {
-            println!("Can not locate commit name {:?}", cn);
-            println!(
-                "in {:?}",
-                proofsinfo
-                    .iter()
-                    .map(|x| x.name.clone())
-                    .collect::<Vec<_>>()
-            );
-        }
-        (idx, column_idx.unwrap() as usize)
+    ) -> Result<(usize, usize)> {
+        let idx = self.get_proof_index(proofsinfo, &cn.name, cn.proof_idx)?;
+        let named_advices = &self
+            .proofs
+            .get(idx)
+            .ok_or_else(|| Error::ProofIndexOutOfRange {
+                name: cn.name.clone(),
+                proof_idx: cn.proof_idx,
+                size: self.proofs.len(),
+            })?
+            .vkey
+            .cs
+            .named_advices;
+        let column_idx = named_advices
+            .iter()
+            .find(|r| r.0 == cn.column_name)
+            .map(|r| r.1)
+            .ok_or_else(|| Error::UnknownCommitmentName {
+                name: cn.name.clone(),
+                proof_idx: cn.proof_idx,
+                column_name: cn.column_name.clone(),
+            })?;
+        Ok((idx, column_idx as usize))
     }
 
     fn get_instance_index(
         &self,
         proofsinfo: &Vec<ProofGenerationInfo>,
         ci: &CommitmentInInstance,
-    ) -> [usize; 3] {
-        let mut idx = 0;
-        for proofinfo in proofsinfo.iter() {
-            if proofinfo.name == ci.name {
-                idx += ci.proof_idx;
-                break;
-            } else {
-                idx += proofinfo.proofs.len()
-            }
-        }
+    ) -> Result<[usize; 3]> {
+        let idx = self.get_proof_index(proofsinfo, &ci.name, ci.proof_idx)?;
         // each commitment as instances are grouped by 3
-        // (proof index, _, first instance of commitment
-        [idx, 0, ci.group_idx * 3 + 1]
+        // (proof index, instance column, first instance of commitment)
+        Ok([idx, ci.column, ci.group_idx * 3 + 1])
     }
 
     pub fn load_commitments_check(
         &mut self,
         proofsinfo: &Vec<ProofGenerationInfo>,
         commits: CommitmentCheck,
-    ) {
+        report: &Report,
+    ) -> Result<()> {
         for eqs in commits.equivalents.iter() {
-            let src = self.get_commitment_index(proofsinfo, &eqs.source);
-            let target = self.get_commitment_index(proofsinfo, &eqs.target);
+            let src = self.get_commitment_index(proofsinfo, &eqs.source)?;
+            let target = self.get_commitment_index(proofsinfo, &eqs.target)?;
             self.equivalents.push([src.0, src.1, target.0, target.1])
         }
         for exp in commits.expose.iter() {
-            let s = self.get_commitment_index(proofsinfo, exp);
+            let s = self.get_commitment_index(proofsinfo, exp)?;
             self.expose.push([s.0, s.1]);
         }
         for absorb in commits.absorb.iter() {
-            let s = self.get_instance_index(proofsinfo, &absorb.instance_idx);
-            let t = self.get_commitment_index(proofsinfo, &absorb.target);
+            let s = self.get_instance_index(proofsinfo, &absorb.instance_idx)?;
+            let t = self.get_commitment_index(proofsinfo, &absorb.target)?;
             self.absorb.push((s, [t.0, t.1]));
         }
+        report.commitments(self.is_final, &self.equivalents, &self.expose, &self.absorb);
+        Ok(())
     }
 
     pub fn build_aggregate_circuit(
         &self,
         params: &Params<E::G1Affine>,
         last_agg_info: Option<Vec<(usize, usize, E::Scalar)>>, // (proof_index, instance_col, hash)
-        use_select_chip: bool,
+        options: AggregatorOptions,
         open_schema: OpenSchema,
         absorb_instance: Vec<(usize, usize, usize, usize)>,
-    ) -> (
-        AggregatorCircuit<E>,
-        Vec<<E as Engine>::Scalar>,
-        Vec<<E as Engine>::Scalar>,
-        <E as Engine>::Scalar,
-    )
+        report: &Report,
+    ) -> Result<AggregateCircuit<E>>
     where
         E: MultiMillerLoop + MultiMillerLoopOnProvePairing,
     {
@@ -198,7 +385,7 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
             .iter()
             .flatten()
             .max_by(|x, y| x.cmp(y))
-            .unwrap();
+            .ok_or_else(|| Error::InvalidArgument("no target proofs to batch".to_string()))?;
 
         last_agg_info.clone().map(|x| {
             target_proof_max_instance[x[0].0] = vec![1];
@@ -221,14 +408,28 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
         let target_aggregator_constant_hash_instance_offset =
             last_agg_info.clone().map_or_else(|| vec![], |x| x.clone());
 
+        // target proofs of unknown schema are verified with the schema of the batch
+        let target_proof_with_shplonk = self
+            .proofs
+            .iter()
+            .enumerate()
+            .filter(|(_, proof)| proof.open_schema.unwrap_or(open_schema) == OpenSchema::Shplonk)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        println!(
+            "target proofs with shplonk: {:?}",
+            target_proof_with_shplonk
+        );
+
         let config = AggregatorConfig {
-            hash: TranscriptHash::Poseidon,
+            hash: options.transcript_hash.transcript_hash(),
             commitment_check: self.equivalents.clone(),
             expose: self.expose.clone(),
             absorb: self.absorb.clone(),
             target_aggregator_constant_hash_instance_offset,
-            target_proof_with_shplonk: vec![],
-            target_proof_with_shplonk_as_default: (open_schema == OpenSchema::Shplonk),
+            target_proof_with_shplonk_as_default: target_proof_with_shplonk.len()
+                == self.proofs.len(),
+            target_proof_with_shplonk,
             target_proof_max_instance,
             is_final_aggregator: self.is_final,
             //prev_aggregator_skip_instance: vec![(1, 1)], // hash get absorbed automatically
@@ -237,12 +438,13 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
                 .as_ref()
                 .map_or_else(|| vec![], |x| vec![(x[0].0, 1)]),
             absorb_instance: last_agg_info.map_or_else(|| vec![], |_| absorb_instance),
-            use_select_chip,
-            circuit_rows_for_pairing: 500000,
+            use_select_chip: options.use_select_chip,
+            circuit_rows_for_pairing: options.circuit_rows_for_pairing,
         };
 
-        let target_params_verifier: ParamsVerifier<E> =
-            params.verifier(max_target_instances).unwrap();
+        let target_params_verifier: ParamsVerifier<E> = params
+            .verifier(max_target_instances)
+            .map_err(Error::halo2)?;
 
         //let params_verifier: ParamsVerifier<E> = params.verifier(max_target_instances).unwrap();
 
@@ -251,6 +453,7 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
         println!("instances {:?}", instances);
         println!("param verifier size {:?}", self.get_agg_instance_size());
         println!("agg config is {:?}", config.absorb_instance);
+        let _phase = report.phase("circuit build", format!("k {}", self.batch_k));
         let timer = start_timer!(|| "build aggregate verify circuit");
         let (circuit, instances, shadow_instance, hash) = build_aggregate_verify_circuit::<E>(
             Arc::new(target_params_verifier),
@@ -264,34 +467,147 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
         );
         end_timer!(timer);
 
-        (circuit, instances, shadow_instance, hash)
+        Ok((circuit, instances, shadow_instance, hash))
+    }
+
+    /// Runs the `MockProver` on the aggregate circuit at `batch_k`. Every failed constraint
+    /// is printed, the error also lists the commitment checks of the batch.
+    fn mock_circuit(
+        &self,
+        circuit: &AggregatorCircuit<E>,
+        instances: &Vec<<E as Engine>::Scalar>,
+    ) -> Result<()> {
+        let timer = start_timer!(|| "mock aggregate circuit");
+        let prover = MockProver::run(self.batch_k as u32, circuit, vec![instances.clone()])
+            .map_err(Error::halo2)?;
+        let verified = prover.verify();
+        end_timer!(timer);
+        verified.map_err(|failures| {
+            for failure in failures.iter() {
+                println!("mock aggregate failure: {:?}", failure);
+            }
+            Error::VerificationFailed(format!(
+                "mock aggregate circuit has {} failures (commitment equiv {:?}, expose {:?}, absorb {:?}), first: {:?}",
+                failures.len(),
+                self.equivalents,
+                self.expose,
+                self.absorb,
+                failures[0]
+            ))
+        })
+    }
+
+    /// Finds the minimal batch k, up to `max_k`, that holds the rows of the aggregate
+    /// circuit. The rows are counted by synthesizing the circuit once, see `count_rows`.
+    /// The built circuit is returned so that `batch_proof` does not build it again.
+    pub fn auto_batch_k(
+        &self,
+        params_cache: &ParamsCache<E>,
+        options: AggregatorOptions,
+        last_agg_info: Option<Vec<(usize, usize, E::Scalar)>>, // (proof_index, instance_col, hash)
+        open_schema: OpenSchema,
+        absorb_instance: Vec<(usize, usize, usize, usize)>,
+        max_k: usize,
+        report: &Report,
+    ) -> Result<(usize, AggregateCircuit<E>)> {
+        let target_params = params_cache.generate_k_params(self.target_k, report)?;
+        let aggregate = self.build_aggregate_circuit(
+            &target_params,
+            last_agg_info,
+            options,
+            open_schema,
+            absorb_instance,
+            report,
+        )?;
+        let (circuit, instances, _, _) = &aggregate;
+
+        let timer = start_timer!(|| "count rows of aggregate circuit");
+        let count = count_rows(circuit, &vec![instances.clone()]).map_err(Error::halo2)?;
+        end_timer!(timer);
+        for (name, rows) in count.regions.iter() {
+            println!("row budget: {} rows in {}", rows, name);
+        }
+        println!(
+            "row budget: {} rows ({} reserved for pairing, {} instances) and {} blinding rows",
+            count.rows,
+            options.circuit_rows_for_pairing,
+            instances.len(),
+            count.blinding_rows
+        );
+        let k = count.min_k();
+        if k > max_k {
+            return Err(Error::InvalidArgument(format!(
+                "aggregate circuit needs k {} and does not fit in k {}",
+                k, max_k
+            )));
+        }
+        println!("row budget: k {} with {} rows fits", k, 1usize << k);
+        Ok((k, aggregate))
+    }
+
+    /// Builds the aggregate circuit and checks it with the `MockProver` instead of proving.
+    pub fn mock_aggregate(
+        &self,
+        params_cache: &ParamsCache<E>,
+        options: AggregatorOptions,
+        last_agg_info: Option<Vec<(usize, usize, E::Scalar)>>, // (proof_index, instance_col, hash)
+        open_schema: OpenSchema,
+        absorb_instance: Vec<(usize, usize, usize, usize)>,
+        report: &Report,
+    ) -> Result<()> {
+        let target_params = params_cache.generate_k_params(self.target_k, report)?;
+        let (circuit, instances, _, _) = self.build_aggregate_circuit(
+            &target_params,
+            last_agg_info,
+            options,
+            open_schema,
+            absorb_instance,
+            report,
+        )?;
+        self.mock_circuit(&circuit, &instances)
     }
 
+    /// Builds and proves the aggregate circuit, unless `aggregate` already holds the circuit
+    /// built by `auto_batch_k`. With `mock` the circuit is checked with the `MockProver`
+    /// first so that a mis-specified batch fails before proving.
     pub fn batch_proof(
         &self,
         proof_piece: ProofPieceInfo,
-        params_cache: &mut ParamsCache<E>,
-        pkey_cache: &mut ProvingKeyCache<E>,
-        use_select_chip: bool,
+        params_cache: &ParamsCache<E>,
+        pkey_cache: &ProvingKeyCache<E>,
+        options: AggregatorOptions,
         hashtype: HashType,
         last_agg_info: Option<Vec<(usize, usize, E::Scalar)>>, // (proof_index, instance_col, hash)
         open_schema: OpenSchema,
         absorb_instance: Vec<(usize, usize, usize, usize)>,
-    ) -> (
+        aggregate: Option<AggregateCircuit<E>>,
+        mock: bool,
+        report: &Report,
+    ) -> Result<(
         ProofPieceInfo,
         Vec<<E as Engine>::Scalar>,
         Vec<u8>,
         Vec<<E as Engine>::Scalar>,
         <E as Engine>::Scalar,
-    ) {
-        let target_params = params_cache.generate_k_params(self.target_k);
-        let (circuit, instances, shadow_instance, hash) = self.build_aggregate_circuit(
-            target_params,
-            last_agg_info.clone(),
-            use_select_chip,
-            open_schema,
-            absorb_instance,
-        );
+    )> {
+        let (circuit, instances, shadow_instance, hash) = match aggregate {
+            Some(aggregate) => aggregate,
+            None => {
+                let target_params = params_cache.generate_k_params(self.target_k, report)?;
+                self.build_aggregate_circuit(
+                    &target_params,
+                    last_agg_info,
+                    options,
+                    open_schema,
+                    absorb_instance,
+                    report,
+                )?
+            }
+        };
+
+        if mock {
+            self.mock_circuit(&circuit, &instances)?;
+        }
 
         let timer = start_timer!(|| "create aggregate proof");
         let transcripts = proof_piece.exec_create_proof::<E, _>(
@@ -302,9 +618,131 @@ impl<E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProve
             params_cache,
             hashtype,
             open_schema,
-        );
+            report,
+        )?;
         end_timer!(timer);
 
-        (proof_piece, instances, transcripts, shadow_instance, hash)
+        report.setting("target_k", self.target_k);
+        report.setting("batch_k", self.batch_k);
+        report.setting("hash", format!("{:?}", hashtype));
+        report.setting("open_schema", format!("{:?}", open_schema));
+        report.proof(
+            &proof_piece.circuit,
+            &proof_piece.transcript,
+            self.batch_k,
+            hashtype,
+            open_schema,
+            &vec![instances.clone()],
+        );
+
+        Ok((proof_piece, instances, transcripts, shadow_instance, hash))
     }
 }
+
+#[test]
+fn validate_commitment_check() {
+    use crate::samples::simple::SimpleCircuit;
+    use halo2_proofs::pairing::bn256::Bn256;
+    use halo2_proofs::pairing::bn256::Fr;
+    use halo2_proofs::pairing::bn256::G1Affine;
+    use halo2_proofs::plonk::keygen_vk;
+
+    let params = Params::<G1Affine>::unsafe_setup::<Bn256>(11);
+    // SimpleCircuit names its first advice column A
+    let vkey = keygen_vk(&params, &SimpleCircuit::<Fr>::default()).unwrap();
+
+    let group = |name: &str, n: usize, instance_size: u32| {
+        let mut info = ProofGenerationInfo::new(name, 11, HashType::Poseidon, OpenSchema::Shplonk);
+        for i in 0..n {
+            info.append_single_proof(ProofPieceInfo::new(
+                name.to_string(),
+                i,
+                instance_size,
+                None,
+            ));
+        }
+        info
+    };
+    let proofsinfo = vec![group("test1", 2, 7), group("test2", 1, 1)];
+    let vkeys = vec![vkey.clone(), vkey.clone(), vkey];
+
+    let name = |name: &str, proof_idx: usize, column_name: &str| CommitmentName {
+        name: name.to_string(),
+        proof_idx,
+        column_name: column_name.to_string(),
+    };
+    let instance = |proof_idx: usize, column: usize, group_idx: usize| CommitmentInInstance {
+        name: "test1".to_string(),
+        proof_idx,
+        column,
+        group_idx,
+    };
+    let check = CommitmentCheck {
+        equivalents: vec![CommitmentEquivPair {
+            source: name("test1", 0, "A"),
+            target: name("test2", 0, "a"),
+        }],
+        expose: vec![name("tset1", 0, "A"), name("test1", 2, "A")],
+        absorb: vec![
+            CommitmentAbsorb {
+                // 7 instances hold 2 groups of 3 after the first one
+                instance_idx: instance(1, 0, 1),
+                target: name("test1", 1, "A"),
+            },
+            CommitmentAbsorb {
+                instance_idx: instance(1, 0, 2),
+                target: name("test1", 1, "A"),
+            },
+            CommitmentAbsorb {
+                instance_idx: instance(0, 1, 0),
+                target: name("test1", 0, "A"),
+            },
+        ],
+    };
+
+    let issues = check.validate(&proofsinfo, &vkeys);
+    let entries = issues.iter().map(|x| x.entry.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        vec![
+            "equivalents[0].target",
+            "expose[0]",
+            "expose[1]",
+            "absorb[1].instance_idx",
+            "absorb[2].instance_idx"
+        ]
+    );
+    assert!(matches!(
+        issues[0].error,
+        Error::UnknownCommitmentName { .. }
+    ));
+    assert_eq!(issues[0].suggestion.as_deref(), Some("A"));
+    assert!(matches!(issues[1].error, Error::UnknownProofGroup { .. }));
+    assert_eq!(issues[1].suggestion.as_deref(), Some("test1"));
+    assert!(matches!(
+        issues[2].error,
+        Error::ProofIndexOutOfRange {
+            proof_idx: 2,
+            size: 2,
+            ..
+        }
+    ));
+    assert!(matches!(
+        issues[3].error,
+        Error::InstanceGroupOutOfRange {
+            column: 0,
+            group_idx: 2,
+            groups: 2,
+            ..
+        }
+    ));
+    // the pieces have a single instance column
+    assert!(matches!(
+        issues[4].error,
+        Error::InstanceGroupOutOfRange {
+            column: 1,
+            groups: 0,
+            ..
+        }
+    ));
+}
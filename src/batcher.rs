@@ -0,0 +1,268 @@
+use crate::args::Accumulator;
+use crate::args::HashType;
+use crate::args::OpenSchema;
+use crate::batch::BatchConfig;
+use crate::batch::CommitmentCheck;
+use crate::error::Error;
+use crate::error::Result;
+use crate::exec::exec_batch_proofs_continuation;
+use crate::exec::exec_batch_proofs_flat;
+use crate::exec::exec_batch_proofs_tree;
+use crate::proof::ParamsCache;
+use crate::proof::ProvingKeyCache;
+use crate::report::Report;
+use halo2_proofs::arithmetic::MultiMillerLoop;
+use halo2_proofs::arithmetic::MultiMillerLoopOnProvePairing;
+use halo2aggregator_s::circuit_verifier::G2AffineBaseHelper;
+use halo2aggregator_s::circuit_verifier::GtHelper;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Largest k tried by `Batcher::auto_k` when no k is given.
+const MAX_AUTO_K: u32 = 26;
+
+/// Files and values produced by a batch run.
+#[derive(Clone)]
+pub struct BatchOutcome<E: MultiMillerLoop> {
+    /// Load info of the (final) batch proof.
+    pub loadinfo: PathBuf,
+    pub instances: Vec<E::Scalar>,
+    pub shadow_instances: Vec<E::Scalar>,
+    /// Solidity aux data, only generated for sha and keccak challenges.
+    pub aux: Option<PathBuf>,
+    /// Merkle records of the continuation hashes, only generated in continuation mode.
+    pub merkle_hashes: Option<PathBuf>,
+}
+
+/// Node of the aggregation tree. `Node` at level 0 batches two segments, at higher
+/// levels it batches the two nodes of the level below. `Final` wraps the root.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TreeNode {
+    Node { level: usize, index: usize },
+    Final,
+}
+
+impl FromStr for TreeNode {
+    type Err = String;
+
+    /// Parses `final` or `<level>.<index>`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "final" {
+            return Ok(TreeNode::Final);
+        }
+        let invalid = || format!("invalid tree node {}, expect <level>.<index> or final", s);
+        let (level, index) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(TreeNode::Node {
+            level: level.parse().map_err(|_| invalid())?,
+            index: index.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Library entry of the batcher.
+///
+/// ```ignore
+/// let outcome = Batcher::<Bn256>::new(params_dir, output_dir, &params_cache, &pkey_cache)
+///     .k(22)
+///     .hash(HashType::Keccak)
+///     .batch("batchsample", &loadinfos, commits)?;
+/// ```
+pub struct Batcher<'a, E: MultiMillerLoop> {
+    pub(crate) params_dir: PathBuf,
+    pub(crate) output_dir: PathBuf,
+    pub(crate) params_cache: &'a ParamsCache<E>,
+    pub(crate) pkey_cache: &'a ProvingKeyCache<E>,
+    pub(crate) k: Option<u32>,
+    pub(crate) hash: HashType,
+    pub(crate) open_schema: OpenSchema,
+    pub(crate) accumulator: Accumulator,
+    pub(crate) config: BatchConfig,
+    pub(crate) mock: bool,
+    pub(crate) auto_k: bool,
+    pub(crate) cont: Option<u32>,
+    pub(crate) resume: bool,
+    pub(crate) extend: bool,
+    pub(crate) finalize: bool,
+    pub(crate) tree: bool,
+    pub(crate) tree_node: Option<TreeNode>,
+    pub(crate) report: Report,
+}
+
+impl<'a, E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing>
+    Batcher<'a, E>
+{
+    pub fn new(
+        params_dir: PathBuf,
+        output_dir: PathBuf,
+        params_cache: &'a ParamsCache<E>,
+        pkey_cache: &'a ProvingKeyCache<E>,
+    ) -> Self {
+        Batcher {
+            params_dir,
+            output_dir,
+            params_cache,
+            pkey_cache,
+            k: None,
+            hash: HashType::Poseidon,
+            open_schema: OpenSchema::Shplonk,
+            accumulator: Accumulator::UseCommitment,
+            config: BatchConfig::default(),
+            mock: false,
+            auto_k: false,
+            cont: None,
+            resume: false,
+            extend: false,
+            finalize: false,
+            tree: false,
+            tree_node: None,
+            report: Report::default(),
+        }
+    }
+
+    /// Circuit size of the batch proof (of the final round in continuation mode). With
+    /// `auto_k` this is the largest k accepted.
+    pub fn k(mut self, k: u32) -> Self {
+        self.k = Some(k);
+        self
+    }
+
+    pub fn hash(mut self, hash: HashType) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    pub fn open_schema(mut self, open_schema: OpenSchema) -> Self {
+        self.open_schema = open_schema;
+        self
+    }
+
+    pub fn accumulator(mut self, accumulator: Accumulator) -> Self {
+        self.accumulator = accumulator;
+        self
+    }
+
+    /// Aggregator knobs of the intermediate and final rounds.
+    pub fn config(mut self, config: BatchConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Whether the aggregator circuit of the final round uses the ecc select chip.
+    pub fn use_ecc_select_chip(mut self, use_ecc_select_chip: bool) -> Self {
+        self.config.final_round.use_select_chip = use_ecc_select_chip;
+        self
+    }
+
+    /// Selects the minimal k of the batch proof (of the final round in continuation mode)
+    /// by synthesizing its aggregate circuit. The chosen k is recorded in the load info.
+    pub fn auto_k(mut self, auto_k: bool) -> Self {
+        self.auto_k = auto_k;
+        self
+    }
+
+    /// Checks every aggregate circuit with the `MockProver` before proving it.
+    pub fn mock(mut self, mock: bool) -> Self {
+        self.mock = mock;
+        self
+    }
+
+    /// Batches the proofs as a continuation flow whose merkle records have the given depth.
+    pub fn continuation(mut self, depth: Option<u32>) -> Self {
+        self.cont = depth;
+        self
+    }
+
+    /// Continues an interrupted continuation batch from its last checkpoint, if any.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Appends the segment proofs to the rec chain of earlier runs with the same name
+    /// instead of batching a complete continuation flow. The chain is started when
+    /// there is none yet.
+    pub fn extend(mut self, extend: bool) -> Self {
+        self.extend = extend;
+        self
+    }
+
+    /// In extend mode, batches the last segment in a final round that closes the chain.
+    pub fn finalize(mut self, finalize: bool) -> Self {
+        self.finalize = finalize;
+        self
+    }
+
+    /// Batches the segments as a binary tree instead of a linear rec chain.
+    pub fn tree(mut self, tree: bool) -> Self {
+        self.tree = tree;
+        self
+    }
+
+    /// In tree mode, proves only the given node so that independent subtrees can be
+    /// proven on different machines sharing the output dir.
+    pub fn tree_node(mut self, node: Option<TreeNode>) -> Self {
+        self.tree_node = node;
+        self
+    }
+
+    /// Report of the run the batch belongs to. Nothing is recorded by default.
+    pub fn report(mut self, report: Report) -> Self {
+        self.report = report;
+        self
+    }
+
+    pub(crate) fn batch_k(&self) -> Result<u32> {
+        match self.k {
+            Some(k) => Ok(k),
+            None if self.auto_k => Ok(MAX_AUTO_K),
+            None => Err(Error::InvalidArgument(
+                "batch k is not provided".to_string(),
+            )),
+        }
+    }
+
+    pub fn batch(
+        &mut self,
+        proof_name: &str,
+        config_files: &Vec<PathBuf>,
+        commits: Vec<CommitmentCheck>,
+    ) -> Result<BatchOutcome<E>> {
+        let proof_name = proof_name.to_string();
+        let intermediate = self.config.intermediate.transcript_hash;
+        let final_round = self.config.final_round.transcript_hash;
+        if (self.tree || self.cont.is_some()) && intermediate != final_round {
+            // the final round verifies segments and intermediate proofs in circuit
+            return Err(Error::HashTypeMismatch {
+                name: format!("{} final round", proof_name),
+                expected: intermediate,
+                found: final_round,
+            });
+        }
+        if self.tree {
+            let node = self.tree_node;
+            return exec_batch_proofs_tree(self, &proof_name, config_files, commits, node);
+        }
+        match self.cont {
+            Some(cont) => {
+                exec_batch_proofs_continuation(self, &proof_name, config_files, commits, cont)
+            }
+            None => exec_batch_proofs_flat(self, &proof_name, config_files, commits),
+        }
+    }
+}
+
+#[test]
+fn tree_node_from_str() {
+    assert_eq!("final".parse::<TreeNode>(), Ok(TreeNode::Final));
+    assert_eq!(
+        "0.3".parse::<TreeNode>(),
+        Ok(TreeNode::Node { level: 0, index: 3 })
+    );
+    assert_eq!(
+        "2.0".parse::<TreeNode>(),
+        Ok(TreeNode::Node { level: 2, index: 0 })
+    );
+    for invalid in ["", "Final", "1", "1.", ".1", "1.2.3", "a.1", "-1.0"] {
+        assert!(invalid.parse::<TreeNode>().is_err(), "{}", invalid);
+    }
+}
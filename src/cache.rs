@@ -0,0 +1,245 @@
+use crate::error::Result;
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Usage statistics of a `SharedCache`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Total time spent loading missed values.
+    pub load_time: Duration,
+    /// Estimated bytes of the cached values.
+    pub bytes_resident: u64,
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} evictions, {:?} loading, {} bytes resident",
+            self.hits, self.misses, self.evictions, self.load_time, self.bytes_resident
+        )
+    }
+}
+
+type Loading = Mutex<HashMap<String, Arc<Mutex<()>>>>;
+
+/// Removes the loading entry of a key when its load finishes, also when the load panics.
+struct LoadingGuard<'a> {
+    loading: &'a Loading,
+    key: &'a str,
+}
+
+impl<'a> Drop for LoadingGuard<'a> {
+    fn drop(&mut self) {
+        self.loading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(self.key);
+    }
+}
+
+struct Entries<V> {
+    lru: LruCache<String, (Arc<V>, u64)>,
+    stats: CacheStats,
+}
+
+/// Lru cache handing out its values as `Arc`s, shareable between threads. Concurrent
+/// requests of a missing key wait on a single load instead of loading it each.
+///
+/// The cache is bounded by its number of entries and optionally by the estimated bytes
+/// of its values, evicting the least recently used values first.
+pub struct SharedCache<V> {
+    entries: Mutex<Entries<V>>,
+    loading: Loading,
+    max_bytes: Option<u64>,
+}
+
+impl<V> SharedCache<V> {
+    pub fn new(cache_size: usize) -> Self {
+        SharedCache {
+            entries: Mutex::new(Entries {
+                lru: LruCache::new(NonZeroUsize::new(cache_size).unwrap()),
+                stats: CacheStats::default(),
+            }),
+            loading: Mutex::new(HashMap::new()),
+            max_bytes: None,
+        }
+    }
+
+    /// Cache bounded by the estimated bytes of its values only.
+    pub fn with_max_bytes(max_bytes: u64) -> Self {
+        SharedCache {
+            entries: Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                stats: CacheStats::default(),
+            }),
+            loading: Mutex::new(HashMap::new()),
+            max_bytes: Some(max_bytes),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.entries.lock().unwrap().stats
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<V>> {
+        let mut entries = self.entries.lock().unwrap();
+        let v = entries.lru.get(key).map(|(v, _)| v.clone());
+        if v.is_some() {
+            entries.stats.hits += 1;
+        }
+        v
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().lru.contains(key)
+    }
+
+    /// Caches the value with its estimated size in bytes. Least recently used values are
+    /// evicted until the cache fits its bounds, the pushed value is always kept.
+    pub fn push(&self, key: String, v: V, bytes: u64) -> Arc<V> {
+        let v = Arc::new(v);
+        let mut entries = self.entries.lock().unwrap();
+        entries.stats.bytes_resident += bytes;
+        if let Some((evicted_key, (_, evicted_bytes))) =
+            entries.lru.push(key.clone(), (v.clone(), bytes))
+        {
+            entries.stats.bytes_resident -= evicted_bytes;
+            // a replaced value of the same key is not an eviction
+            if evicted_key != key {
+                entries.stats.evictions += 1;
+            }
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            while entries.stats.bytes_resident > max_bytes && entries.lru.len() > 1 {
+                let (key, (_, evicted_bytes)) = entries.lru.pop_lru().unwrap();
+                log::info!("evict {} of {} bytes from cache", key, evicted_bytes);
+                entries.stats.bytes_resident -= evicted_bytes;
+                entries.stats.evictions += 1;
+            }
+        }
+        v
+    }
+
+    /// Returns the cached value of the key, or loads and caches it with the estimated
+    /// size returned by `load`. While a key is loaded other requests of it block until
+    /// the load finishes. A request waiting on a load that failed or panicked loads the
+    /// key itself.
+    pub fn get_or_load<F: FnOnce() -> Result<(V, u64)>>(
+        &self,
+        key: &str,
+        load: F,
+    ) -> Result<Arc<V>> {
+        if let Some(v) = self.get(key) {
+            return Ok(v);
+        }
+        let key_lock = self
+            .loading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        // the lock only guards the load, a panicked load leaves nothing to recover
+        let _guard = key_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        // dropped before `_guard`, the entry is gone once waiters are released
+        let _loading = LoadingGuard {
+            loading: &self.loading,
+            key,
+        };
+        // loaded by the request we waited on
+        if let Some(v) = self.get(key) {
+            return Ok(v);
+        }
+        let start = Instant::now();
+        let loaded = load();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.stats.misses += 1;
+            entries.stats.load_time += start.elapsed();
+        }
+        loaded.map(|(v, bytes)| self.push(key.to_string(), v, bytes))
+    }
+}
+
+#[test]
+fn cache_loads_key_once_for_concurrent_requests() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let cache = SharedCache::<usize>::new(2);
+    let loads = AtomicUsize::new(0);
+    let (cache_ref, loads_ref) = (&cache, &loads);
+    let values = std::thread::scope(|s| {
+        let handles = (0..2)
+            .map(|_| {
+                s.spawn(move || {
+                    cache_ref
+                        .get_or_load("key", || {
+                            loads_ref.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(100));
+                            Ok((42, 8))
+                        })
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&values[0], &values[1]));
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.bytes_resident, 8);
+}
+
+#[test]
+fn cache_evicts_least_recently_used_by_bytes() {
+    let cache = SharedCache::<usize>::with_max_bytes(100);
+    cache.push("a".to_string(), 1, 40);
+    cache.push("b".to_string(), 2, 40);
+    // a is now more recently used than b
+    assert!(cache.get("a").is_some());
+    cache.push("c".to_string(), 3, 40);
+    assert!(cache.contains("a"));
+    assert!(!cache.contains("b"));
+    assert!(cache.contains("c"));
+    // a value over the bound is kept alone
+    cache.push("d".to_string(), 4, 200);
+    assert!(cache.contains("d"));
+    assert!(!cache.contains("a") && !cache.contains("c"));
+    let stats = cache.stats();
+    assert_eq!(stats.evictions, 3);
+    assert_eq!(stats.bytes_resident, 200);
+    assert_eq!(stats.hits, 1);
+    // replacing the value of a key is not an eviction
+    cache.push("d".to_string(), 5, 50);
+    assert_eq!(cache.stats().evictions, 3);
+    assert_eq!(cache.stats().bytes_resident, 50);
+}
+
+#[test]
+fn cache_recovers_from_panicked_load() {
+    let cache = SharedCache::<usize>::new(2);
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.get_or_load("key", || panic!("load failed"))
+    }));
+    assert!(panicked.is_err());
+    assert!(cache.loading.lock().unwrap().is_empty());
+    assert_eq!(*cache.get_or_load("key", || Ok((7, 1))).unwrap(), 7);
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.hits, 0);
+}
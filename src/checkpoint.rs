@@ -0,0 +1,133 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::proof::ProofPieceInfo;
+use crate::utils::field_from_hex;
+use crate::utils::field_to_hex;
+use crate::utils::write_file_atomic;
+use ff::PrimeField;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Progress of a continuation batch, saved after each rec round so that an interrupted
+/// run can pick up from the last completed round. Field elements are stored as hex of
+/// their repr.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ContinuationCheckpoint {
+    pub name: String,
+    /// Index of the last round whose agg proof is saved in `<name>.rec.loadinfo.json`.
+    pub last_index: usize,
+    pub hashes: Vec<String>,
+    pub final_hashes: Vec<String>,
+    pub instance0: String,
+    pub last_agg_piece: ProofPieceInfo,
+}
+
+impl ContinuationCheckpoint {
+    pub fn new<F: PrimeField>(
+        name: &str,
+        last_index: usize,
+        hashes: &Vec<F>,
+        final_hashes: &Vec<F>,
+        instance0: &F,
+        last_agg_piece: &ProofPieceInfo,
+    ) -> Self {
+        ContinuationCheckpoint {
+            name: name.to_string(),
+            last_index,
+            hashes: hashes.iter().map(field_to_hex).collect(),
+            final_hashes: final_hashes.iter().map(field_to_hex).collect(),
+            instance0: field_to_hex(instance0),
+            last_agg_piece: last_agg_piece.clone(),
+        }
+    }
+
+    pub fn checkpoint_file(cache_folder: &Path, name: &str) -> PathBuf {
+        cache_folder.join(format!("{}.checkpoint.json", name))
+    }
+
+    pub fn save(&self, cache_folder: &Path) -> Result<PathBuf> {
+        let cache_file = Self::checkpoint_file(cache_folder, &self.name);
+        log::info!("write continuation checkpoint {:?}", cache_file);
+        let json = serde_json::to_vec_pretty(self).map_err(Error::serialize(&cache_file))?;
+        write_file_atomic(&cache_file, &json)?;
+        Ok(cache_file)
+    }
+
+    /// Returns `None` when no checkpoint of the task has been saved.
+    pub fn load(cache_folder: &Path, name: &str) -> Result<Option<Self>> {
+        let cache_file = Self::checkpoint_file(cache_folder, name);
+        if !cache_file.exists() {
+            return Ok(None);
+        }
+        log::info!("read continuation checkpoint {:?}", cache_file);
+        let fd = std::fs::File::open(&cache_file).map_err(Error::io(&cache_file))?;
+        serde_json::from_reader(fd)
+            .map(Some)
+            .map_err(Error::parse(&cache_file))
+    }
+
+    pub fn remove(cache_folder: &Path, name: &str) -> Result<()> {
+        let cache_file = Self::checkpoint_file(cache_folder, name);
+        if cache_file.exists() {
+            std::fs::remove_file(&cache_file).map_err(Error::io(&cache_file))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_hashes<F: PrimeField>(&self) -> Result<Vec<F>> {
+        self.hashes.iter().map(|x| field_from_hex(x)).collect()
+    }
+
+    pub fn get_final_hashes<F: PrimeField>(&self) -> Result<Vec<F>> {
+        self.final_hashes
+            .iter()
+            .map(|x| field_from_hex(x))
+            .collect()
+    }
+
+    pub fn get_instance0<F: PrimeField>(&self) -> Result<F> {
+        field_from_hex(&self.instance0)
+    }
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    use halo2_proofs::pairing::bn256::Fr;
+
+    let cache_folder = Path::new("output/test_checkpoint");
+    std::fs::create_dir_all(cache_folder).unwrap();
+    ContinuationCheckpoint::remove(cache_folder, "test").unwrap();
+    assert!(ContinuationCheckpoint::load(cache_folder, "test")
+        .unwrap()
+        .is_none());
+
+    let hashes = vec![Fr::from(1u64), Fr::from(2u64)];
+    let final_hashes = vec![Fr::from(3u64)];
+    let instance0 = -Fr::from(4u64);
+    let piece = ProofPieceInfo::new("test.rec".to_string(), 3, 1, None);
+    let checkpoint =
+        ContinuationCheckpoint::new("test", 3, &hashes, &final_hashes, &instance0, &piece);
+    let file = checkpoint.save(cache_folder).unwrap();
+    assert_eq!(
+        file,
+        ContinuationCheckpoint::checkpoint_file(cache_folder, "test")
+    );
+    assert!(!cache_folder.join("test.checkpoint.json.tmp").exists());
+
+    let loaded = ContinuationCheckpoint::load(cache_folder, "test")
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.name, "test");
+    assert_eq!(loaded.last_index, 3);
+    assert_eq!(loaded.get_hashes::<Fr>().unwrap(), hashes);
+    assert_eq!(loaded.get_final_hashes::<Fr>().unwrap(), final_hashes);
+    assert_eq!(loaded.get_instance0::<Fr>().unwrap(), instance0);
+    assert_eq!(loaded.last_agg_piece.transcript, piece.transcript);
+    assert_eq!(loaded.last_agg_piece.instance_size, piece.instance_size);
+
+    ContinuationCheckpoint::remove(cache_folder, "test").unwrap();
+    assert!(ContinuationCheckpoint::load(cache_folder, "test")
+        .unwrap()
+        .is_none());
+}
@@ -5,14 +5,29 @@ use super::args::ArgBuilder;
 
 pub trait CommandBuilder: ArgBuilder {
     fn append_params_subcommand(app: App) -> App {
-        let command = Command::new("params").arg(Self::zkwasm_k_arg().required(true));
+        let command = Command::new("params")
+            .subcommand_required(true)
+            .subcommand(Command::new("gen").arg(Self::zkwasm_k_arg().required(true)))
+            .subcommand(Command::new("info").arg(Self::zkwasm_k_arg().required(true)))
+            .subcommand(Command::new("list"))
+            .subcommand(
+                Command::new("import")
+                    .arg(Self::ptau_arg())
+                    .arg(Self::zkwasm_k_arg().required(true)),
+            )
+            .subcommand(
+                Command::new("downsize")
+                    .arg(Self::from_k_arg())
+                    .arg(Self::zkwasm_k_arg().required(true)),
+            )
+            .subcommand(Command::new("verify"));
         app.subcommand(command)
     }
 
     fn append_setup_subcommand(app: App) -> App {
         let command = Command::new("setup")
             .arg(Self::output_path_arg())
-            .arg(Self::zkwasm_k_arg());
+            .arg(Self::zkwasm_k_arg().required(true));
         app.subcommand(command)
     }
 
@@ -25,13 +40,47 @@ pub trait CommandBuilder: ArgBuilder {
             .arg(Self::commits_info_arg())
             .arg(Self::accumulator())
             .arg(Self::proof_load_info_arg())
-            .arg(Self::cont_arg());
+            .arg(Self::cont_arg())
+            .arg(Self::resume_arg())
+            .arg(Self::extend_arg())
+            .arg(Self::finalize_arg())
+            .arg(Self::tree_arg())
+            .arg(Self::tree_node_arg())
+            .arg(Self::mock_arg())
+            .arg(Self::auto_k_arg())
+            .arg(Self::batch_config_arg());
+        app.subcommand(command)
+    }
+
+    fn append_prove_subcommand(app: App) -> App {
+        let command = Command::new("prove")
+            .arg(Self::openschema())
+            .arg(Self::proof_load_info_arg());
+        app.subcommand(command)
+    }
+
+    fn append_export_vkey_subcommand(app: App) -> App {
+        let command = Command::new("export-vkey").arg(Self::proof_load_info_arg());
+        app.subcommand(command)
+    }
+
+    fn append_check_subcommand(app: App) -> App {
+        let command = Command::new("check")
+            .arg(Self::commits_info_arg())
+            .arg(Self::proof_load_info_arg());
+        app.subcommand(command)
+    }
+
+    fn append_plan_subcommand(app: App) -> App {
+        let command = Command::new("plan")
+            .subcommand_required(true)
+            .subcommand(Command::new("run").arg(Self::plan_arg()));
         app.subcommand(command)
     }
 
     fn append_verify_subcommand(app: App) -> App {
         let command = Command::new("verify")
-            .arg(Self::hashtype())
+            .arg(Self::optional_hashtype())
             .arg(Self::proof_load_info_arg());
 
         app.subcommand(command)
@@ -40,7 +89,7 @@ pub trait CommandBuilder: ArgBuilder {
     fn append_generate_solidity_verifier(app: App) -> App {
         let command = Command::new("solidity")
             .arg(Self::zkwasm_k_arg())
-            .arg(Self::hashtype())
+            .arg(Self::optional_hashtype())
             .arg(Self::commits_info_arg())
             .arg(Self::sol_dir_arg())
             .arg(Self::proof_load_info_arg());
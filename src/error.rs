@@ -0,0 +1,159 @@
+use crate::args::HashType;
+use crate::args::OpenSchema;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error on {path:?}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("can not parse config {path:?}: {source}")]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("can not write {path:?}: {source}")]
+    Serialize {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("can not locate commitment {column_name} of proof {proof_idx} in group {name}")]
+    UnknownCommitmentName {
+        name: String,
+        proof_idx: usize,
+        column_name: String,
+    },
+
+    #[error("can not locate proof group {name} in {candidates:?}")]
+    UnknownProofGroup {
+        name: String,
+        candidates: Vec<String>,
+    },
+
+    #[error("proof index {proof_idx} out of range for group {name} with {size} proofs")]
+    ProofIndexOutOfRange {
+        name: String,
+        proof_idx: usize,
+        size: usize,
+    },
+
+    #[error("instance group {group_idx} in column {column} of proof {proof_idx} in group {name} out of range, the column has {groups} groups")]
+    InstanceGroupOutOfRange {
+        name: String,
+        proof_idx: usize,
+        column: usize,
+        group_idx: usize,
+        groups: usize,
+    },
+
+    #[error("hashtype mismatch in {name}: expected {expected:?}, found {found:?}")]
+    HashTypeMismatch {
+        name: String,
+        expected: HashType,
+        found: HashType,
+    },
+
+    #[error("open schema mismatch in {name}: expected {expected:?}, found {found:?}")]
+    OpenSchemaMismatch {
+        name: String,
+        expected: OpenSchema,
+        found: OpenSchema,
+    },
+
+    #[error("k mismatch in {name}: expected {expected}, found {found}")]
+    KMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("digest mismatch of {path:?}: recorded {expected}, found {found}")]
+    DigestMismatch {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+
+    #[error("size mismatch of {path:?}: recorded {expected} bytes, found {found} bytes")]
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        found: u64,
+    },
+
+    #[error("params {path:?} {reason}, refusing them as trusted params are required")]
+    UntrustedParams { path: PathBuf, reason: String },
+
+    #[error("verification failed: {0}")]
+    VerificationFailed(String),
+
+    #[error("halo2 error: {0}")]
+    Halo2(String),
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+impl Error {
+    pub fn io(path: &Path) -> impl FnOnce(std::io::Error) -> Error {
+        let path = path.to_path_buf();
+        move |source| Error::IoError { path, source }
+    }
+
+    pub fn parse(path: &Path) -> impl FnOnce(serde_json::Error) -> Error {
+        let path = path.to_path_buf();
+        move |source| Error::ConfigParse { path, source }
+    }
+
+    pub fn serialize(path: &Path) -> impl FnOnce(serde_json::Error) -> Error {
+        let path = path.to_path_buf();
+        move |source| Error::Serialize { path, source }
+    }
+
+    pub fn halo2<E: std::fmt::Debug>(e: E) -> Error {
+        Error::Halo2(format!("{:?}", e))
+    }
+
+    /// Process exit code used by the cli for each error category.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::IoError { .. } | Error::Serialize { .. } => 2,
+            Error::ConfigParse { .. } => 3,
+            Error::UnknownCommitmentName { .. }
+            | Error::UnknownProofGroup { .. }
+            | Error::ProofIndexOutOfRange { .. }
+            | Error::InstanceGroupOutOfRange { .. } => 4,
+            Error::HashTypeMismatch { .. } | Error::OpenSchemaMismatch { .. } => 5,
+            Error::KMismatch { .. } => 6,
+            Error::VerificationFailed(_) => 7,
+            Error::Halo2(_) => 8,
+            Error::InvalidArgument(_) => 9,
+            Error::DigestMismatch { .. } | Error::SizeMismatch { .. } => 10,
+            Error::UntrustedParams { .. } => 11,
+        }
+    }
+}
+
+/// Fails with a not-found `IoError` when the file is missing. Used before
+/// handing paths to loaders in halo2aggregator-s that panic instead.
+pub(crate) fn ensure_exists(path: &Path) -> Result<()> {
+    if path.exists() {
+        Ok(())
+    } else {
+        Err(Error::IoError {
+            path: path.to_path_buf(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "file not found"),
+        })
+    }
+}
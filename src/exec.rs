@@ -3,13 +3,34 @@ use crate::args::HashType;
 use crate::args::OpenSchema;
 use crate::batch::BatchInfo;
 use crate::batch::CommitmentCheck;
+use crate::batcher::BatchOutcome;
+use crate::batcher::Batcher;
+use crate::batcher::TreeNode;
+use crate::checkpoint::ContinuationCheckpoint;
+use crate::error::ensure_exists;
+use crate::error::Error;
+use crate::error::Result;
+use crate::manifest::FileManifest;
+use crate::manifest::Origin;
+use crate::params::downsize_params;
+use crate::params::import_ptau;
 use crate::proof::load_or_build_unsafe_params;
+use crate::proof::read_vkey_full;
+use crate::proof::store_vkey;
+use crate::proof::vkey_file_name;
 use crate::proof::ParamsCache;
 use crate::proof::ProofGenerationInfo;
 use crate::proof::ProofInfo;
 use crate::proof::ProofPieceInfo;
 use crate::proof::ProvingKeyCache;
+use crate::report::Report;
+use halo2_proofs::arithmetic::CurveAffine;
+use halo2_proofs::arithmetic::MultiMillerLoop;
+use halo2_proofs::arithmetic::MultiMillerLoopOnProvePairing;
+use halo2_proofs::poly::commitment::Params;
 use halo2_proofs::poly::commitment::ParamsVerifier;
+use halo2aggregator_s::circuit_verifier::G2AffineBaseHelper;
+use halo2aggregator_s::circuit_verifier::GtHelper;
 use halo2aggregator_s::circuits::utils::calc_hash;
 use halo2aggregator_s::circuits::utils::store_instance;
 use halo2aggregator_s::circuits::utils::TranscriptHash;
@@ -26,146 +47,628 @@ use halo2_proofs::arithmetic::BaseExt;
 use halo2_proofs::dev::MockProver;
 */
 use crate::utils::construct_merkle_records;
+use crate::utils::file_digest;
+use ark_std::end_timer;
+use ark_std::start_timer;
 use ff::PrimeField;
-use halo2_proofs::pairing::bn256::Bn256;
-use halo2_proofs::pairing::bn256::G1Affine;
 use log::info;
+use rayon::prelude::*;
+use serde::Serialize;
 use sha2::Digest;
 
+use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::PathBuf;
+use std::time::Instant;
 
-pub fn generate_k_params(
+pub fn generate_k_params<E: MultiMillerLoop>(
     aggregate_k: u32,
     params_dir: &PathBuf,
-    params_cache: &mut ParamsCache<Bn256>,
-) {
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Result<()> {
     info!("Generating K Params file");
 
     // Setup Aggregate Circuit Params
     {
         let params_path = &params_dir.join(format!("K{}.params", aggregate_k));
-        load_or_build_unsafe_params::<Bn256>(aggregate_k as usize, params_path, params_cache)
+        load_or_build_unsafe_params::<E>(aggregate_k as usize, params_path, params_cache, report)?
     };
+    Ok(())
 }
 
-pub fn exec_batch_proofs(
-    params_cache: &mut ParamsCache<Bn256>,
-    pkey_cache: &mut ProvingKeyCache<Bn256>,
-    proof_name: &String,
-    output_dir: &PathBuf,
+fn write_params_file<C: CurveAffine>(
+    params: &Params<C>,
+    params_file: &PathBuf,
+    origin: Origin,
+    report: &Report,
+) -> Result<()> {
+    if params_file.exists() {
+        return Err(Error::InvalidArgument(format!(
+            "params file {:?} already exists",
+            params_file
+        )));
+    }
+    log::info!("write params K={} to {:?}", params.k, params_file);
+    let mut fd = std::fs::File::create(params_file).map_err(Error::io(params_file))?;
+    params.write(&mut fd).map_err(Error::io(params_file))?;
+    FileManifest::record(params_file, origin)?;
+    report.output(params_file);
+    Ok(())
+}
+
+/// Imports the params of size k from a (bn254) powers of tau ceremony file into the
+/// params dir. Returns the path of the params file.
+pub fn exec_params_import(
+    ptau: &PathBuf,
+    k: u32,
     params_dir: &PathBuf,
-    config_files: Vec<PathBuf>,
-    commits: Vec<CommitmentCheck>,
-    hash: HashType,
+    report: &Report,
+) -> Result<PathBuf> {
+    let params_file = params_dir.join(format!("K{}.params", k));
+    let params = import_ptau(ptau, k)?;
+    report.input(ptau);
+    let origin = Origin::Ceremony {
+        ptau: ptau
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    };
+    write_params_file(&params, &params_file, origin, report)?;
+    Ok(params_file)
+}
+
+/// Derives the params of size k from the larger params of the params dir. Returns the
+/// path of the params file.
+pub fn exec_params_downsize<E: MultiMillerLoop>(
+    from: u32,
+    k: u32,
+    params_dir: &PathBuf,
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Result<PathBuf> {
+    let params_file = params_dir.join(format!("K{}.params", k));
+    let from_file = params_dir.join(format!("K{}.params", from));
+    ensure_exists(&from_file)?;
+    let params = downsize_params(&params_cache.generate_k_params(from as usize, report)?, k)?;
+    let origin = Origin::Downsized {
+        from: format!("K{}.params", from),
+        source: FileManifest::load(&from_file)?.map(|x| Box::new(x.origin)),
+    };
+    write_params_file(&params, &params_file, origin, report)?;
+    Ok(params_file)
+}
+
+/// Files of the params dir whose name ends with the suffix, sorted by name.
+fn params_dir_files(params_dir: &PathBuf, suffix: &str) -> Result<Vec<PathBuf>> {
+    let mut files = std::fs::read_dir(params_dir)
+        .map_err(Error::io(params_dir))?
+        .map(|entry| entry.map(|x| x.path()).map_err(Error::io(params_dir)))
+        .collect::<Result<Vec<_>>>()?;
+    files.retain(|file| file.to_string_lossy().ends_with(suffix));
+    files.sort();
+    Ok(files)
+}
+
+fn file_name(file: &PathBuf) -> String {
+    file.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Circuit data files of the params dir grouped by the k of their circuit, which is the
+/// k of the params file they depend on.
+fn circuits_by_k<E: MultiMillerLoop>(
+    params_dir: &PathBuf,
+    report: &Report,
+) -> Result<BTreeMap<u32, Vec<String>>> {
+    let mut circuits: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for file in params_dir_files(params_dir, ".circuit.data")? {
+        let vkey = read_vkey_full::<E>(&file, report)?;
+        circuits
+            .entry(vkey.domain.k())
+            .or_default()
+            .push(file_name(&file));
+    }
+    Ok(circuits)
+}
+
+fn describe_origin(file: &PathBuf) -> Result<String> {
+    Ok(match FileManifest::load(file)? {
+        Some(manifest) => format!("{:?}", manifest.origin),
+        None => "unknown (no manifest)".to_string(),
+    })
+}
+
+/// Reads the k from the header of the params file, which starts with k as a little
+/// endian u32 followed by the points.
+fn read_params_k(params_file: &PathBuf) -> Result<u32> {
+    let mut fd = std::fs::File::open(params_file).map_err(Error::io(params_file))?;
+    let mut k = [0u8; 4];
+    fd.read_exact(&mut k).map_err(Error::io(params_file))?;
+    Ok(u32::from_le_bytes(k))
+}
+
+/// Prints the k, points, size and origin of the params file of size k and the circuits of
+/// the params dir that depend on it. Only the header of the params file is read.
+pub fn exec_params_info<E: MultiMillerLoop>(
     k: u32,
-    cont: Option<u32>,
-    use_ecc_select_chip: bool,
+    params_dir: &PathBuf,
+    report: &Report,
+) -> Result<()> {
+    let params_file = params_dir.join(format!("K{}.params", k));
+    ensure_exists(&params_file)?;
+    let size = std::fs::metadata(&params_file)
+        .map_err(Error::io(&params_file))?
+        .len();
+    let origin = describe_origin(&params_file)?;
+    let file_k = read_params_k(&params_file)?;
+    if file_k != k {
+        return Err(Error::KMismatch {
+            name: file_name(&params_file),
+            expected: k as usize,
+            found: file_k as usize,
+        });
+    }
+    println!("params file: {:?}", params_file);
+    println!("k: {}", file_k);
+    // the params hold the 2^k G1 points in monomial and in lagrange basis
+    println!(
+        "G1 points: {} (and {} in lagrange basis)",
+        1u64 << file_k,
+        1u64 << file_k
+    );
+    // [1]G2 and [s]G2 used by the verifier
+    println!("G2 points: 2");
+    println!("size: {} bytes", size);
+    println!("origin: {}", origin);
+    let circuits = circuits_by_k::<E>(params_dir, report)?;
+    println!(
+        "circuits: {:?}",
+        circuits.get(&k).cloned().unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Prints a line for each params file of the params dir with its size, origin and the
+/// circuits that depend on it.
+pub fn exec_params_list<E: MultiMillerLoop>(params_dir: &PathBuf, report: &Report) -> Result<()> {
+    let circuits = circuits_by_k::<E>(params_dir, report)?;
+    for file in params_dir_files(params_dir, ".params")? {
+        let name = file_name(&file);
+        let k = name
+            .strip_prefix("K")
+            .and_then(|x| x.strip_suffix(".params"))
+            .and_then(|x| x.parse::<u32>().ok());
+        let size = std::fs::metadata(&file).map_err(Error::io(&file))?.len();
+        let dependents = k
+            .and_then(|k| circuits.get(&k).cloned())
+            .unwrap_or_default();
+        println!(
+            "{}: {} bytes, {}, circuits: {:?}",
+            name,
+            size,
+            describe_origin(&file)?,
+            dependents
+        );
+    }
+    Ok(())
+}
+
+/// Checks every params, circuit data and vkey file of the params dir against its manifest and
+/// prints the result of each file. Returns the number of files that fail the check or
+/// have no manifest.
+pub fn exec_params_verify(params_dir: &PathBuf) -> Result<usize> {
+    let mut files = params_dir_files(params_dir, ".params")?;
+    files.append(&mut params_dir_files(params_dir, ".circuit.data")?);
+    files.append(&mut params_dir_files(params_dir, ".vkey.data")?);
+
+    let mut problems = 0;
+    for file in files.iter() {
+        let name = file_name(file);
+        match FileManifest::load(file)? {
+            Some(manifest) => match manifest.check(file) {
+                Ok(()) => println!(
+                    "{}: ok, {} bytes, {:?}",
+                    name, manifest.size, manifest.origin
+                ),
+                Err(e) => {
+                    problems += 1;
+                    println!("{}: {}", name, e);
+                }
+            },
+            None => {
+                problems += 1;
+                println!("{}: no manifest", name);
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Proves every piece of the load infos from the witness files in the output dir and
+/// saves the updated load infos there. Returns the paths of the saved load infos.
+pub fn exec_prove<E: MultiMillerLoop>(
+    params_cache: &ParamsCache<E>,
+    pkey_cache: &ProvingKeyCache<E>,
+    output_dir: &PathBuf,
+    config_files: &Vec<PathBuf>,
     open_schema: OpenSchema,
-    accumulator: Accumulator,
-) {
-    if cont.is_some() {
-        let cont = cont.unwrap();
-        let start_circuit_prefix = format!("{}.start", proof_name);
-        let rec_circuit_prefix = format!("{}.rec", proof_name);
-        let final_circuit_prefix = format!("{}.final", proof_name);
-
-        exec_batch_proofs_continuation(
-            params_cache,
-            pkey_cache,
-            proof_name,
-            &start_circuit_prefix,
-            &rec_circuit_prefix,
-            &final_circuit_prefix,
-            output_dir,
+    report: &Report,
+) -> Result<Vec<PathBuf>> {
+    let mut loadinfos = vec![];
+    for config_file in config_files.iter() {
+        let mut proofloadinfo = ProofGenerationInfo::load(config_file, report)?;
+        for (i, proof_piece) in proofloadinfo.proofs.iter().enumerate() {
+            println!(
+                "prove {} piece {} from {}",
+                proofloadinfo.name, i, proof_piece.witness
+            );
+            let (instances, transcripts) = proof_piece.exec_create_proof_from_witness(
+                proofloadinfo.k,
+                output_dir,
+                pkey_cache,
+                params_cache,
+                proofloadinfo.hashtype,
+                open_schema,
+                report,
+            )?;
+            proof_piece.save_proof_data(&instances, &transcripts, output_dir, report)?;
+            report.proof(
+                &proof_piece.circuit,
+                &proof_piece.transcript,
+                proofloadinfo.k,
+                proofloadinfo.hashtype,
+                open_schema,
+                &instances,
+            );
+        }
+        proofloadinfo.open_schema = Some(open_schema);
+        proofloadinfo.record_digests(&params_cache.cache_dir)?;
+        loadinfos.push(proofloadinfo.save(output_dir, report)?);
+    }
+    Ok(loadinfos)
+}
+
+/// Outcome of verifying a proof of a load info.
+#[derive(Clone, Serialize, Debug)]
+pub struct ProofVerification {
+    pub loadinfo: PathBuf,
+    /// Index of the proof in the load info, `None` when the load info failed as a whole,
+    /// e.g. on missing files or mismatched digests.
+    pub proof_idx: Option<usize>,
+    pub error: Option<String>,
+    pub seconds: f64,
+}
+
+impl ProofVerification {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Verifies the proofs of the load info in parallel against a shared params verifier.
+fn verify_loadinfo<E: MultiMillerLoop>(
+    params_dir: &PathBuf,
+    output_dir: &PathBuf,
+    config_file: &PathBuf,
+    hash: Option<HashType>,
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Result<Vec<ProofVerification>> {
+    let proofloadinfo = ProofGenerationInfo::load(config_file, report)?;
+    proofloadinfo.check_recorded(hash, None)?;
+    proofloadinfo.check_digests(params_dir)?;
+    let proofs: Vec<ProofInfo<E>> =
+        ProofInfo::load_proof(output_dir, params_dir, &proofloadinfo, report)?;
+    let params = load_or_build_unsafe_params::<E>(
+        proofloadinfo.k,
+        &params_dir.join(format!("K{}.params", proofloadinfo.k)),
+        params_cache,
+        report,
+    )?;
+    let public_inputs_size = proofs
+        .iter()
+        .flat_map(|proof| proof.instances.iter().map(|x| x.len()))
+        .max()
+        .unwrap_or(0);
+    let params_verifier: ParamsVerifier<E> =
+        params.verifier(public_inputs_size).map_err(Error::halo2)?;
+
+    // proofs of unknown schema are taken as shplonk
+    let open_schema = proofloadinfo.open_schema.unwrap_or(OpenSchema::Shplonk);
+    let timer = start_timer!(|| format!("native verify {} proofs", proofs.len()));
+    let results = proofs
+        .par_iter()
+        .enumerate()
+        .map(|(i, proof)| {
+            let start = Instant::now();
+            let verified = proof.verify_proof(&params_verifier, open_schema, report);
+            ProofVerification {
+                loadinfo: config_file.clone(),
+                proof_idx: Some(i),
+                error: verified.err().map(|e| e.to_string()),
+                seconds: start.elapsed().as_secs_f64(),
+            }
+        })
+        .collect();
+    end_timer!(timer);
+    Ok(results)
+}
+
+/// Verifies every proof of the load infos without stopping at the first failure and
+/// returns the outcome of each proof.
+pub fn exec_verify<E: MultiMillerLoop>(
+    params_dir: &PathBuf,
+    output_dir: &PathBuf,
+    config_files: &Vec<PathBuf>,
+    hash: Option<HashType>,
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Vec<ProofVerification> {
+    let mut results = vec![];
+    for config_file in config_files.iter() {
+        let start = Instant::now();
+        match verify_loadinfo(
             params_dir,
-            config_files,
-            commits,
-            hash,
-            k,
-            cont,
-            use_ecc_select_chip,
-            open_schema,
-            // accumulator,
-        );
-    } else {
-        exec_batch_proofs_flat(
-            params_cache,
-            pkey_cache,
-            proof_name,
             output_dir,
-            params_dir,
-            config_files,
-            commits,
+            config_file,
             hash,
-            k,
-            use_ecc_select_chip,
-            open_schema,
-            accumulator,
-        );
+            params_cache,
+            report,
+        ) {
+            Ok(mut verified) => results.append(&mut verified),
+            Err(e) => results.push(ProofVerification {
+                loadinfo: config_file.clone(),
+                proof_idx: None,
+                error: Some(e.to_string()),
+                seconds: start.elapsed().as_secs_f64(),
+            }),
+        }
     }
+    report.verifications(&results);
+    results
 }
 
-pub fn exec_batch_proofs_flat(
-    params_cache: &mut ParamsCache<Bn256>,
-    pkey_cache: &mut ProvingKeyCache<Bn256>,
-    proof_name: &String,
+/// Extracts the standalone vkey of every piece of the load infos from its circuit data
+/// into the params dir and saves the load infos referencing them in the output dir.
+/// Returns the paths of the saved load infos.
+pub fn exec_export_vkey<E: MultiMillerLoop>(
+    params_dir: &PathBuf,
     output_dir: &PathBuf,
+    config_files: &Vec<PathBuf>,
+    report: &Report,
+) -> Result<Vec<PathBuf>> {
+    let mut loadinfos = vec![];
+    for config_file in config_files.iter() {
+        let mut proofloadinfo = ProofGenerationInfo::load(config_file, report)?;
+        for proof_piece in proofloadinfo.proofs.iter_mut() {
+            let vkey_name = vkey_file_name(&proof_piece.circuit);
+            let vkey = read_vkey_full::<E>(&params_dir.join(&proof_piece.circuit), report)?;
+            store_vkey::<E>(&vkey, &params_dir.join(&vkey_name), report)?;
+            println!("export vkey of {} to {}", proof_piece.circuit, vkey_name);
+            proof_piece.vkey_file_digest = Some(file_digest(&params_dir.join(&vkey_name))?);
+            proof_piece.vkey = Some(vkey_name);
+        }
+        loadinfos.push(proofloadinfo.save(output_dir, report)?);
+    }
+    Ok(loadinfos)
+}
+
+/// Validates the commits sheets against the proof groups of the load infos without
+/// building any circuit. Prints every problem found and returns their number.
+pub fn exec_check<E: MultiMillerLoop>(
     params_dir: &PathBuf,
-    config_files: Vec<PathBuf>,
-    commits: Vec<CommitmentCheck>,
-    hash: HashType,
-    k: u32,
-    use_ecc_select_chip: bool,
-    open_schema: OpenSchema,
-    accumulator: Accumulator,
-) {
+    config_files: &Vec<PathBuf>,
+    commits: &Vec<PathBuf>,
+    report: &Report,
+) -> Result<usize> {
+    let proofsinfo = config_files
+        .iter()
+        .map(|config| ProofGenerationInfo::load(config, report))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut vkeys = vec![];
+    for info in proofsinfo.iter() {
+        for proof_info in info.proofs.iter() {
+            vkeys.push(proof_info.read_vkey::<E>(params_dir, report)?);
+        }
+    }
+    // same order as the proofs held by the batcher
+    vkeys.reverse();
+
+    let mut problems = 0;
+    for commits_file in commits.iter() {
+        let issues = CommitmentCheck::load(commits_file, report)?.validate(&proofsinfo, &vkeys);
+        for issue in issues.iter() {
+            println!("{:?} {}", commits_file, issue);
+        }
+        problems += issues.len();
+    }
+    Ok(problems)
+}
+
+/// Loads the target proof groups and checks that they can be batched together:
+/// every group must use the transcript hash verified in the circuit and share the same k.
+fn load_target_proofs<E: MultiMillerLoop>(
+    output_dir: &PathBuf,
+    params_dir: &PathBuf,
+    config_files: &Vec<PathBuf>,
+    transcript_hash: HashType,
+    report: &Report,
+) -> Result<(Vec<ProofGenerationInfo>, Vec<ProofInfo<E>>, usize)> {
     let mut target_k = None;
     let proofsinfo = config_files
         .iter()
         .map(|config| {
-            let proofloadinfo = ProofGenerationInfo::load(config);
-            // target batch proof needs to use poseidon hash
-            assert_eq!(proofloadinfo.hashtype, HashType::Poseidon);
-            proofloadinfo
+            let proofloadinfo = ProofGenerationInfo::load(config, report)?;
+            // target batch proof needs to use the hash of the aggregator circuit
+            if proofloadinfo.hashtype != transcript_hash {
+                return Err(Error::HashTypeMismatch {
+                    name: proofloadinfo.name.clone(),
+                    expected: transcript_hash,
+                    found: proofloadinfo.hashtype,
+                });
+            }
+            proofloadinfo.check_digests(params_dir)?;
+            Ok(proofloadinfo)
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut proofs = vec![];
+    for info in proofsinfo.iter() {
+        println!("batching {} proofs:", proofsinfo.len());
+        match target_k {
+            // proofs in the same batch needs to have same size
+            Some(k) if k != info.k => {
+                return Err(Error::KMismatch {
+                    name: info.name.clone(),
+                    expected: k,
+                    found: info.k,
+                })
+            }
+            _ => target_k = Some(info.k),
+        }
+        proofs.append(&mut ProofInfo::load_proof(
+            &output_dir,
+            &params_dir,
+            &info,
+            report,
+        )?);
+    }
 
-    let mut proofs = proofsinfo
-        .iter()
-        .map(|info| {
-            println!("batching {} proofs:", proofsinfo.len());
-            target_k = target_k.map_or(Some(info.k), |x| {
-                // proofs in the same batch needs to have same size
-                assert_eq!(x, info.k);
-                Some(x)
-            });
-            ProofInfo::load_proof(&output_dir, &params_dir, &info)
-        })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>();
+    let target_k = target_k
+        .ok_or_else(|| Error::InvalidArgument("no proof load info provided".to_string()))?;
 
     proofs.reverse();
 
-    let param_file = format!("K{}.params", k as usize);
+    Ok((proofsinfo, proofs, target_k))
+}
 
-    let mut batchinfo = BatchInfo::<Bn256> {
+/// Generates the solidity aux data of a final batch proof that uses sha or keccak challenges.
+fn exec_solidity_aux_gen<
+    E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+>(
+    params_cache: &ParamsCache<E>,
+    output_dir: &PathBuf,
+    params_dir: &PathBuf,
+    proof_generation_info: &ProofGenerationInfo,
+    report: &Report,
+) -> Result<PathBuf> {
+    let proof: Vec<ProofInfo<E>> =
+        ProofInfo::load_proof(&output_dir, &params_dir, proof_generation_info, report)?;
+
+    println!("generate aux data for proof: {:?}", proof_generation_info);
+
+    // setup batch params
+    let params = load_or_build_unsafe_params::<E>(
+        proof_generation_info.k as usize,
+        &params_dir.join(format!("K{}.params", proof_generation_info.k)),
+        params_cache,
+        report,
+    )?;
+
+    // the final instance size is 1
+    let params_verifier: ParamsVerifier<E> = params.verifier(1).map_err(Error::halo2)?;
+
+    let aux_file = output_dir.join(format!(
+        "{}.{}.aux.data",
+        &proof_generation_info.name.clone(),
+        0
+    ));
+
+    // generate solidity aux data
+    // it only makes sense if the transcript challenge is poseidon
+    match proof_generation_info.hashtype {
+        HashType::Sha => {
+            solidity_aux_gen::<_, sha2::Sha256>(
+                &params_verifier,
+                &proof[0].vkey,
+                &proof[0].instances[0],
+                proof[0].transcripts.clone(),
+                &aux_file,
+            );
+        }
+        HashType::Keccak => {
+            solidity_aux_gen::<_, sha3::Keccak256>(
+                &params_verifier,
+                &proof[0].vkey,
+                &proof[0].instances[0],
+                proof[0].transcripts.clone(),
+                &aux_file,
+            );
+        }
+        HashType::Poseidon => {
+            return Err(Error::InvalidArgument(
+                "solidity aux data requires sha or keccak challenge".to_string(),
+            ))
+        }
+    }
+    report.output(&aux_file);
+    Ok(aux_file)
+}
+
+pub fn exec_batch_proofs_flat<
+    E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+>(
+    batcher: &mut Batcher<E>,
+    proof_name: &String,
+    config_files: &Vec<PathBuf>,
+    commits: Vec<CommitmentCheck>,
+) -> Result<BatchOutcome<E>> {
+    let k = batcher.batch_k()?;
+    let hash = batcher.hash;
+    let mock = batcher.mock;
+    let output_dir = &batcher.output_dir;
+    let params_dir = &batcher.params_dir;
+    let report = &batcher.report;
+    let (proofsinfo, proofs, target_k) = load_target_proofs(
+        output_dir,
+        params_dir,
+        config_files,
+        batcher.config.final_round.transcript_hash,
+        report,
+    )?;
+
+    let commits = commits
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::InvalidArgument("commits sheet is not provided".to_string()))?;
+
+    let mut batchinfo = BatchInfo::<E> {
         proofs,
-        target_k: target_k.unwrap(),
+        target_k,
         batch_k: k as usize,
         equivalents: vec![],
         absorb: vec![],
         expose: vec![],
-        is_final: accumulator == Accumulator::UseHash,
+        is_final: batcher.accumulator == Accumulator::UseHash,
     };
-    batchinfo.load_commitments_check(&proofsinfo, commits[0].clone());
+    batchinfo.load_commitments_check(&proofsinfo, commits, report)?;
+
+    let mut aggregate = None;
+    if batcher.auto_k {
+        let (batch_k, circuit) = batchinfo.auto_batch_k(
+            batcher.params_cache,
+            batcher.config.final_round,
+            None,
+            batcher.open_schema,
+            vec![],
+            k as usize,
+            report,
+        )?;
+        batchinfo.batch_k = batch_k;
+        aggregate = Some(circuit);
+    }
 
     // Singleton batch
     let mut proof_generation_info = ProofGenerationInfo::new(
         format!("{}", proof_name).as_str(),
         batchinfo.batch_k as usize,
         hash,
+        batcher.open_schema,
     );
 
     let proof_piece = ProofPieceInfo::new(
@@ -176,217 +679,318 @@ pub fn exec_batch_proofs_flat(
     );
     let (agg_proof_piece, instances, transcripts, shadow_instances, _) = batchinfo.batch_proof(
         proof_piece,
-        params_cache,
-        pkey_cache,
-        use_ecc_select_chip,
+        batcher.params_cache,
+        batcher.pkey_cache,
+        batcher.config.final_round,
         hash,
         None,
-        open_schema,
+        batcher.open_schema,
         vec![],
-    );
+        aggregate,
+        mock,
+        report,
+    )?;
 
-    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir);
+    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir, report)?;
 
     proof_generation_info.append_single_proof(agg_proof_piece);
-    proof_generation_info.save(output_dir);
-
-    store_instance(
-        &vec![shadow_instances],
-        &output_dir.join(format!(
-            "{}.{}.shadowinstance.data",
-            &proof_generation_info.name.clone(),
-            0
-        )),
-    );
-
-    if hash == HashType::Sha || hash == HashType::Keccak {
-        let proof: Vec<ProofInfo<Bn256>> =
-            ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info);
-
-        println!("generate aux data for proof: {:?}", proof_generation_info);
-
-        // setup batch params
-        let params = load_or_build_unsafe_params::<Bn256>(
-            proof_generation_info.k as usize,
-            &params_dir.join(param_file),
-            params_cache,
-        );
+    proof_generation_info.record_digests(params_dir)?;
+    let loadinfo = proof_generation_info.save(output_dir, report)?;
+
+    let shadow_instance_file = output_dir.join(format!(
+        "{}.{}.shadowinstance.data",
+        &proof_generation_info.name.clone(),
+        0
+    ));
+    store_instance(&vec![shadow_instances.clone()], &shadow_instance_file);
+    report.output(&shadow_instance_file);
+
+    let aux = if hash == HashType::Sha || hash == HashType::Keccak {
+        Some(exec_solidity_aux_gen(
+            batcher.params_cache,
+            output_dir,
+            params_dir,
+            &proof_generation_info,
+            report,
+        )?)
+    } else {
+        None
+    };
 
-        // the final instance size is 1
-        let params_verifier: ParamsVerifier<Bn256> = params.verifier(1).unwrap();
-
-        // generate solidity aux data
-        // it only makes sense if the transcript challenge is poseidon
-        match hash {
-            HashType::Sha => {
-                solidity_aux_gen::<_, sha2::Sha256>(
-                    &params_verifier,
-                    &proof[0].vkey,
-                    &proof[0].instances[0],
-                    proof[0].transcripts.clone(),
-                    &output_dir.join(format!(
-                        "{}.{}.aux.data",
-                        &proof_generation_info.name.clone(),
-                        0
-                    )),
-                );
-            }
-            HashType::Keccak => {
-                solidity_aux_gen::<_, sha3::Keccak256>(
-                    &params_verifier,
-                    &proof[0].vkey,
-                    &proof[0].instances[0],
-                    proof[0].transcripts.clone(),
-                    &output_dir.join(format!(
-                        "{}.{}.aux.data",
-                        &proof_generation_info.name.clone(),
-                        0
-                    )),
-                );
-            }
-            HashType::Poseidon => unreachable!(),
-        }
-    }
+    Ok(BatchOutcome {
+        loadinfo,
+        instances,
+        shadow_instances,
+        aux,
+        merkle_hashes: None,
+    })
 }
 
-pub fn exec_batch_proofs_continuation(
-    params_cache: &mut ParamsCache<Bn256>,
-    pkey_cache: &mut ProvingKeyCache<Bn256>,
+pub fn exec_batch_proofs_continuation<
+    E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+>(
+    batcher: &mut Batcher<E>,
     proof_name: &String,
-    start_circuit_prefix: &String,
-    rec_circuit_prefix: &String,
-    final_circuit_prefix: &String,
-    output_dir: &PathBuf,
-    params_dir: &PathBuf,
-    config_files: Vec<PathBuf>,
+    config_files: &Vec<PathBuf>,
     commits: Vec<CommitmentCheck>,
-    hash: HashType,
-    k: u32,
     cont: u32,
-    use_ecc_select_chip: bool,
-    open_schema: OpenSchema,
-    // accumulator: Accumulator,
-) {
-    let mut target_k = None;
-    let proofsinfo = config_files
-        .iter()
-        .map(|config| {
-            let proofloadinfo = ProofGenerationInfo::load(config);
-            // target batch proof needs to use poseidon hash
-            assert_eq!(proofloadinfo.hashtype, HashType::Poseidon);
-            proofloadinfo
-        })
-        .collect::<Vec<_>>();
-
-    let mut proofs = proofsinfo
-        .iter()
-        .map(|info| {
-            println!("batching {} proofs:", proofsinfo.len());
-            target_k = target_k.map_or(Some(info.k), |x| {
-                // proofs in the same batch needs to have same size
-                assert_eq!(x, info.k);
-                Some(x)
-            });
-            ProofInfo::load_proof(&output_dir, &params_dir, &info)
-        })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>();
-
-    proofs.reverse();
-
-    let param_file = format!("K{}.params", k as usize);
+) -> Result<BatchOutcome<E>> {
+    let k = batcher.batch_k()?;
+    let hash = batcher.hash;
+    let open_schema = batcher.open_schema;
+    let mock = batcher.mock;
+    let intermediate = batcher.config.intermediate;
+    let output_dir = &batcher.output_dir;
+    let params_dir = &batcher.params_dir;
+    let report = &batcher.report;
+    let params_cache = batcher.params_cache;
+    let pkey_cache = batcher.pkey_cache;
+    let start_circuit_prefix = format!("{}.start", proof_name);
+    let rec_circuit_prefix = format!("{}.rec", proof_name);
+    let final_circuit_prefix = format!("{}.final", proof_name);
+
+    let (proofsinfo, proofs, target_k) = load_target_proofs(
+        output_dir,
+        params_dir,
+        config_files,
+        intermediate.transcript_hash,
+        report,
+    )?;
 
     let proof_index = |x: usize| proofs.len() - x - 1;
 
-    assert!(proofs.len() >= 3);
-
-    // first round where there is no previous aggregation proof
-    // if it is not the last round we used the target k as the batch k
-    // so that the rec agg circuit can then be aggregate again with the next
-    // guest proof
-    let mut batchinfo = BatchInfo::<Bn256> {
-        proofs: vec![proofs[0].clone()],
-        target_k: target_k.unwrap(),
-        batch_k: target_k.unwrap(),
-        equivalents: vec![],
-        absorb: vec![],
-        expose: vec![],
-        is_final: false,
-    };
-
-    let mut proof_generation_info = ProofGenerationInfo::new(
-        format!("{}.rec", proof_name).as_str(),
-        batchinfo.batch_k as usize,
-        HashType::Poseidon,
-    );
-
-    {
-        // load commitments check for the first round
-        let round_info = proofsinfo[0].get_single_info("single", proof_index(0));
-        batchinfo.load_commitments_check(&vec![round_info], commits[0].clone());
+    if commits.len() < 3 {
+        return Err(Error::InvalidArgument(format!(
+            "continuation batching requires init, rec and final commits sheets, found {}",
+            commits.len()
+        )));
     }
 
-    let proof_piece = ProofPieceInfo::new(
-        format!("{}.start", proof_name),
-        0,
-        batchinfo.get_agg_instance_size() as u32,
-        Some(start_circuit_prefix.clone()),
-    );
-
-    let (agg_proof_piece, instances, transcripts, _, last_hash) = batchinfo.batch_proof(
-        proof_piece,
-        params_cache,
-        pkey_cache,
-        true,
-        proof_generation_info.hashtype,
-        None, // no previous agg
-        open_schema,
-        vec![],
-    );
-
-    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir);
+    let acc_proof_info =
+        ProofGenerationInfo::new("acc", target_k, intermediate.transcript_hash, open_schema);
+    let rec_loadinfo_file = output_dir.join(format!("{}.rec.loadinfo.json", proof_name));
 
-    // start recording the hash of first round
-    let mut hashes = vec![last_hash];
-    let mut final_hashes = vec![instances[0]]; // the first instance is the hash of the vkey of this round
+    // in extend mode the rec chain of earlier runs is always continued
+    let checkpoint = if batcher.resume || batcher.extend {
+        ContinuationCheckpoint::load(output_dir, proof_name)?
+    } else {
+        None
+    };
+    // the hashes of the chain are only kept by the checkpoint, which is removed when the
+    // chain is finalized, so such a chain can not be extended without starting over
+    if batcher.extend && checkpoint.is_none() && rec_loadinfo_file.exists() {
+        return Err(Error::InvalidArgument(format!(
+            "{:?} has no checkpoint to extend, the rec chain was finalized or its checkpoint removed",
+            rec_loadinfo_file
+        )));
+    }
 
-    proof_generation_info.append_single_proof(agg_proof_piece.clone());
-    proof_generation_info.save(output_dir);
+    // round r of the rec chain batches the segment proofs[r - segment_offset], since
+    // the new segments of extend mode follow the rounds of earlier runs
+    let segment_offset = match &checkpoint {
+        Some(checkpoint) if batcher.extend => checkpoint.last_index + 1,
+        _ => 0,
+    };
+    let finalize = !batcher.extend || batcher.finalize;
+    let rounds = segment_offset + proofs.len();
+
+    if proofs.is_empty() || (finalize && rounds < 3) {
+        return Err(Error::InvalidArgument(format!(
+            "continuation batching requires at least 3 proofs, found {}",
+            rounds
+        )));
+    }
 
-    let acc_proof_info =
-        ProofGenerationInfo::new("acc", batchinfo.batch_k as usize, HashType::Poseidon);
+    // the last segment is batched in the final round
+    let rec_rounds = if finalize { rounds - 1 } else { rounds };
+
+    let (
+        mut proof_generation_info,
+        mut hashes,
+        mut final_hashes,
+        mut instance0,
+        mut last_agg_piece,
+        mut last_instances,
+        start,
+    ) = match checkpoint {
+        Some(checkpoint) => {
+            if checkpoint.last_index + 1 > rec_rounds {
+                return Err(Error::InvalidArgument(format!(
+                    "checkpoint of {} is at round {} but only {} proofs are provided",
+                    proof_name,
+                    checkpoint.last_index,
+                    proofs.len()
+                )));
+            }
+            println!("resume continuation after round {}", checkpoint.last_index);
+            let mut proof_generation_info = ProofGenerationInfo::load(&rec_loadinfo_file, report)?;
+            // the segments continue the chain with the k, hash and schema it was created with
+            proof_generation_info
+                .check_recorded(Some(intermediate.transcript_hash), Some(target_k))?;
+            if let Some(expected) = proof_generation_info
+                .open_schema
+                .filter(|x| *x != open_schema)
+            {
+                return Err(Error::OpenSchemaMismatch {
+                    name: proof_generation_info.name.clone(),
+                    expected,
+                    found: open_schema,
+                });
+            }
+            // drop rounds saved after the checkpoint, they are proved again
+            proof_generation_info
+                .proofs
+                .truncate(checkpoint.last_index + 1);
+            let instance0 = checkpoint.get_instance0::<E::Scalar>()?;
+
+            // validate the last saved agg proof before building on top of it
+            let agg_proof = ProofInfo::<E>::load_proof(
+                &output_dir,
+                &params_dir,
+                &proof_generation_info
+                    .get_single_info(&proof_generation_info.name, checkpoint.last_index)?,
+                report,
+            )?
+            .remove(0);
+            if agg_proof.instances[0][0] != instance0 {
+                return Err(Error::VerificationFailed(format!(
+                    "instance of saved rec proof {} does not match the checkpoint",
+                    checkpoint.last_index
+                )));
+            }
+            let params_verifier: ParamsVerifier<E> = params_cache
+                .generate_k_params(target_k, report)?
+                .verifier(agg_proof.instances[0].len())
+                .map_err(Error::halo2)?;
+            agg_proof.verify_proof(&params_verifier, open_schema, report)?;
+
+            (
+                proof_generation_info,
+                checkpoint.get_hashes::<E::Scalar>()?,
+                checkpoint.get_final_hashes::<E::Scalar>()?,
+                instance0,
+                checkpoint.last_agg_piece.clone(),
+                agg_proof.instances[0].clone(),
+                checkpoint.last_index + 1,
+            )
+        }
+        None => {
+            // first round where there is no previous aggregation proof
+            // if it is not the last round we used the target k as the batch k
+            // so that the rec agg circuit can then be aggregate again with the next
+            // guest proof
+            let mut batchinfo = BatchInfo::<E> {
+                proofs: vec![proofs[0].clone()],
+                target_k,
+                batch_k: target_k,
+                equivalents: vec![],
+                absorb: vec![],
+                expose: vec![],
+                is_final: false,
+            };
+
+            let mut proof_generation_info = ProofGenerationInfo::new(
+                format!("{}.rec", proof_name).as_str(),
+                batchinfo.batch_k as usize,
+                intermediate.transcript_hash,
+                open_schema,
+            );
+
+            {
+                // load commitments check for the first round
+                let round_info = proofsinfo[0].get_single_info("single", proof_index(0))?;
+                batchinfo.load_commitments_check(&vec![round_info], commits[0].clone(), report)?;
+            }
 
-    let mut last_agg_piece = agg_proof_piece;
+            let proof_piece = ProofPieceInfo::new(
+                format!("{}.start", proof_name),
+                0,
+                batchinfo.get_agg_instance_size() as u32,
+                Some(start_circuit_prefix.clone()),
+            );
+
+            let (agg_proof_piece, instances, transcripts, _, last_hash) = batchinfo.batch_proof(
+                proof_piece,
+                params_cache,
+                pkey_cache,
+                intermediate,
+                proof_generation_info.hashtype,
+                None, // no previous agg
+                open_schema,
+                vec![],
+                None,
+                mock,
+                report,
+            )?;
+
+            agg_proof_piece.save_proof_data(
+                &vec![instances.clone()],
+                &transcripts,
+                &output_dir,
+                report,
+            )?;
+
+            // start recording the hash of first round
+            let hashes = vec![last_hash];
+            let final_hashes = vec![instances[0]]; // the first instance is the hash of the vkey of this round
+
+            proof_generation_info.append_single_proof(agg_proof_piece.clone());
+            proof_generation_info.record_digests(params_dir)?;
+            proof_generation_info.save(output_dir, report)?;
+
+            ContinuationCheckpoint::new(
+                proof_name,
+                0,
+                &hashes,
+                &final_hashes,
+                &instances[0],
+                &agg_proof_piece,
+            )
+            .save(output_dir)?;
+
+            (
+                proof_generation_info,
+                hashes,
+                final_hashes,
+                instances[0],
+                agg_proof_piece,
+                instances,
+                1,
+            )
+        }
+    };
 
     // second round (1 .. k-2)
     let mut agg_proof =
-        ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info)[0].clone();
+        ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info, report)?[start - 1]
+            .clone();
 
-    let mut instance0 = instances[0];
-
-    for i in 1..proofs.len() - 1 {
+    for i in start..rec_rounds {
         // recursive round where there is a aggregation proof and a guest proof.
         // If it is not the last round we used the target k as the batch k
         // so that the rec agg circuit can then be aggregate again with the next
         // guest proof
         println!("generate rec proofs {}", i);
-        batchinfo = BatchInfo::<Bn256> {
-            proofs: vec![proofs[i].clone(), agg_proof],
-            target_k: target_k.unwrap(),
-            batch_k: target_k.unwrap(),
+        let segment = i - segment_offset;
+        let mut batchinfo = BatchInfo::<E> {
+            proofs: vec![proofs[segment].clone(), agg_proof],
+            target_k,
+            batch_k: target_k,
             equivalents: vec![],
             absorb: vec![],
             expose: vec![],
             is_final: false,
         };
 
-        let round_info = proofsinfo[0].get_single_info("single", proof_index(i));
+        let round_info = proofsinfo[0].get_single_info("single", proof_index(segment))?;
         let mut acc_proof_info = acc_proof_info.clone();
         acc_proof_info.append_single_proof(last_agg_piece.clone());
 
-        batchinfo.load_commitments_check(&vec![round_info, acc_proof_info], commits[1].clone());
+        batchinfo.load_commitments_check(
+            &vec![round_info, acc_proof_info],
+            commits[1].clone(),
+            report,
+        )?;
 
         let proof_piece = ProofPieceInfo::new(
             format!("{}.rec", proof_name),
@@ -398,37 +1002,66 @@ pub fn exec_batch_proofs_continuation(
             proof_piece.clone(),
             params_cache,
             pkey_cache,
-            true,
+            intermediate,
             proof_generation_info.hashtype,
             Some(vec![(1, 0, instance0)]),
             open_schema,
             vec![(0, 0, 1, 1)], // absorb instance commitment since continuation mod has same instances for all segments
-        );
-
-        agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir);
+            None,
+            mock,
+            report,
+        )?;
+
+        agg_proof_piece.save_proof_data(
+            &vec![instances.clone()],
+            &transcripts,
+            &output_dir,
+            report,
+        )?;
 
         instance0 = instances[0];
 
         proof_generation_info.append_single_proof(agg_proof_piece);
-        proof_generation_info.save(output_dir);
+        proof_generation_info.record_digests(params_dir)?;
+        proof_generation_info.save(output_dir, report)?;
 
         hashes.push(last_hash);
         final_hashes.push(instances[0]);
+        last_instances = instances;
 
         agg_proof =
-            ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info)[i].clone();
+            ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info, report)?[i]
+                .clone();
 
         last_agg_piece = proof_piece;
+
+        ContinuationCheckpoint::new(
+            proof_name,
+            i,
+            &hashes,
+            &final_hashes,
+            &instance0,
+            &last_agg_piece,
+        )
+        .save(output_dir)?;
     }
 
-    proof_generation_info =
-        ProofGenerationInfo::new(format!("{}.final", proof_name).as_str(), k as usize, hash);
+    if !finalize {
+        // the chain stays open so that later segments can extend it
+        return Ok(BatchOutcome {
+            loadinfo: rec_loadinfo_file,
+            instances: last_instances,
+            shadow_instances: vec![],
+            aux: None,
+            merkle_hashes: None,
+        });
+    }
 
     // Now we processing the final round where we provid the batch_k which could be slightly
     // bigger than the target_k since it will uses a non-select ecc circuit
-    batchinfo = BatchInfo::<Bn256> {
+    let mut batchinfo = BatchInfo::<E> {
         proofs: vec![proofs[proofs.len() - 1].clone(), agg_proof],
-        target_k: target_k.unwrap(),
+        target_k,
         batch_k: k as usize,
         equivalents: vec![],
         absorb: vec![],
@@ -438,12 +1071,38 @@ pub fn exec_batch_proofs_continuation(
 
     {
         // load commits for last round
-        let round_info = proofsinfo[0].get_single_info("single", proof_index(proofs.len() - 1));
+        let round_info = proofsinfo[0].get_single_info("single", proof_index(proofs.len() - 1))?;
         let mut acc_proof_info = acc_proof_info.clone();
         acc_proof_info.append_single_proof(last_agg_piece.clone());
-        batchinfo.load_commitments_check(&vec![round_info, acc_proof_info], commits[2].clone());
+        batchinfo.load_commitments_check(
+            &vec![round_info, acc_proof_info],
+            commits[2].clone(),
+            report,
+        )?;
     }
 
+    let mut aggregate = None;
+    if batcher.auto_k {
+        let (batch_k, circuit) = batchinfo.auto_batch_k(
+            params_cache,
+            batcher.config.final_round,
+            Some(vec![(1, 0, instance0)]),
+            open_schema,
+            vec![(0, 0, 1, 1)],
+            k as usize,
+            report,
+        )?;
+        batchinfo.batch_k = batch_k;
+        aggregate = Some(circuit);
+    }
+
+    proof_generation_info = ProofGenerationInfo::new(
+        format!("{}.final", proof_name).as_str(),
+        batchinfo.batch_k,
+        hash,
+        open_schema,
+    );
+
     // Last round
     let proof_piece = ProofPieceInfo::new(
         format!("{}.final", proof_name),
@@ -457,23 +1116,27 @@ pub fn exec_batch_proofs_continuation(
             proof_piece,
             params_cache,
             pkey_cache,
-            use_ecc_select_chip,
+            batcher.config.final_round,
             proof_generation_info.hashtype,
             Some(vec![(1, 0, instance0)]),
             open_schema,
             vec![(0, 0, 1, 1)], // absorb instance commitment since continuation mod has same instances for all segments
-        );
+            aggregate,
+            mock,
+            report,
+        )?;
 
-    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir);
+    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir, report)?;
 
     proof_generation_info.append_single_proof(agg_proof_piece);
-    proof_generation_info.save(output_dir);
+    proof_generation_info.record_digests(params_dir)?;
+    let loadinfo = proof_generation_info.save(output_dir, report)?;
 
     hashes.push(last_hash);
 
     let len = 2u32.pow(cont) as usize;
 
-    let final_hashes_expected = calc_hash::<G1Affine>(
+    let final_hashes_expected = calc_hash::<E::G1Affine>(
         hashes[0..3].try_into().unwrap(),
         hashes[0..3].try_into().unwrap(),
         len,
@@ -481,75 +1144,469 @@ pub fn exec_batch_proofs_continuation(
 
     let mut final_hashes_merkle: Vec<[u8; 32]> = final_hashes_expected
         .iter()
-        .map(|x| x.to_repr())
+        .map(|x| {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(x.to_repr().as_ref());
+            bytes
+        })
         .collect::<Vec<_>>();
 
-    construct_merkle_records(
-        &output_dir.join(format!("{}.{}.hashes", &proof_name, len)),
-        &mut final_hashes_merkle,
-        cont as usize,
-    );
+    let merkle_hashes = output_dir.join(format!("{}.{}.hashes", &proof_name, len));
+    construct_merkle_records(&merkle_hashes, &mut final_hashes_merkle, cont as usize)?;
 
-    store_instance(
-        &vec![shadow_instances],
-        &output_dir.join(format!(
-            "{}.{}.shadowinstance.data",
-            &proof_generation_info.name.clone(),
-            0
-        )),
+    // the rec chain is complete, a later resume starts over
+    ContinuationCheckpoint::remove(output_dir, proof_name)?;
+
+    let shadow_instance_file = output_dir.join(format!(
+        "{}.{}.shadowinstance.data",
+        &proof_generation_info.name.clone(),
+        0
+    ));
+    store_instance(&vec![shadow_instances.clone()], &shadow_instance_file);
+    report.output(&shadow_instance_file);
+
+    let aux = if hash == HashType::Sha || hash == HashType::Keccak {
+        Some(exec_solidity_aux_gen(
+            params_cache,
+            output_dir,
+            params_dir,
+            &proof_generation_info,
+            report,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(BatchOutcome {
+        loadinfo,
+        instances,
+        shadow_instances,
+        aux,
+        merkle_hashes: Some(merkle_hashes),
+    })
+}
+
+/// Batches the segments as a binary tree. Level 0 nodes batch two adjacent segments with
+/// the leaf commits sheet (groups `left` and `right`), exposing the commitments at the
+/// borders of the node. A node of a higher level batches the two nodes below it (`left`,
+/// `right`) with the node commits sheet, which checks the exposed border between them
+/// with `equivalents` and exposes the outer borders again. The root is wrapped by a
+/// final round (group `root`) using the final commits sheet.
+///
+/// Each node is saved as its own load info, valid nodes already present in the output dir
+/// are reused, hence independent subtrees can be proven separately by selecting a single
+/// node. A node records the digest of the proofs it batches and is proven again once they
+/// change; a selected node (or the final round) requires the nodes below it to be valid.
+pub fn exec_batch_proofs_tree<
+    E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+>(
+    batcher: &mut Batcher<E>,
+    proof_name: &String,
+    config_files: &Vec<PathBuf>,
+    commits: Vec<CommitmentCheck>,
+    node: Option<TreeNode>,
+) -> Result<BatchOutcome<E>> {
+    let k = batcher.batch_k()?;
+    let hash = batcher.hash;
+    let open_schema = batcher.open_schema;
+    let mock = batcher.mock;
+    let intermediate = batcher.config.intermediate;
+    let output_dir = &batcher.output_dir;
+    let params_dir = &batcher.params_dir;
+    let report = &batcher.report;
+    let params_cache = batcher.params_cache;
+    let pkey_cache = batcher.pkey_cache;
+
+    let (proofsinfo, proofs, target_k) = load_target_proofs(
+        output_dir,
+        params_dir,
+        config_files,
+        intermediate.transcript_hash,
+        report,
+    )?;
+
+    let proof_index = |x: usize| proofs.len() - x - 1;
+
+    if proofs.len() < 2 || !proofs.len().is_power_of_two() {
+        return Err(Error::InvalidArgument(format!(
+            "tree batching requires a power of two number of proofs, found {}",
+            proofs.len()
+        )));
+    }
+
+    if commits.len() < 3 {
+        return Err(Error::InvalidArgument(format!(
+            "tree batching requires leaf, node and final commits sheets, found {}",
+            commits.len()
+        )));
+    }
+
+    let levels = proofs.len().trailing_zeros() as usize;
+    let node_loadinfo_file = |level: usize, index: usize| {
+        output_dir.join(format!(
+            "{}.tree.{}.{}.loadinfo.json",
+            proof_name, level, index
+        ))
+    };
+    let segment_info = |name: &str, i: usize| proofsinfo[0].get_single_info(name, proof_index(i));
+    // digest of the proof pieces batched by a node, either two segments or two child nodes
+    let node_digest = |level: usize, index: usize| -> Result<String> {
+        let mut pieces = vec![];
+        if level == 0 {
+            pieces.append(&mut segment_info("left", 2 * index)?.proofs);
+            pieces.append(&mut segment_info("right", 2 * index + 1)?.proofs);
+        } else {
+            for child in [2 * index, 2 * index + 1] {
+                let child_file = node_loadinfo_file(level - 1, child);
+                ensure_exists(&child_file)?;
+                pieces.append(&mut ProofGenerationInfo::load(&child_file, report)?.proofs);
+            }
+        }
+        let commit = if level == 0 { &commits[0] } else { &commits[1] };
+        tree_node_digest(commit, &pieces, output_dir)
+    };
+    let node_is_valid = |level: usize, index: usize| -> Result<bool> {
+        tree_node_is_valid(
+            batcher,
+            &node_loadinfo_file(level, index),
+            &node_digest(level, index)?,
+            target_k,
+            intermediate.transcript_hash,
+        )
+    };
+    // a selected node is batched from the nodes below it, which are not proven again
+    let require_valid_node = |level: usize, index: usize| -> Result<()> {
+        if node_is_valid(level, index)? {
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument(format!(
+                "tree node {}.{} is missing or stale, prove it first",
+                level, index
+            )))
+        }
+    };
+
+    let mut outcome = None;
+    for level in 0..levels {
+        // number of segments covered by a node of this level
+        let span = 2usize.pow(level as u32 + 1);
+        for index in 0..proofs.len() / span {
+            let this_node = TreeNode::Node { level, index };
+            match node {
+                Some(node) if node != this_node => continue,
+                Some(_) if level > 0 => {
+                    require_valid_node(level - 1, 2 * index)?;
+                    require_valid_node(level - 1, 2 * index + 1)?;
+                }
+                Some(_) => (),
+                None if node_is_valid(level, index)? => continue,
+                None => (),
+            }
+            let input_digest = node_digest(level, index)?;
+            println!("generate tree proof {}.{}", level, index);
+
+            let first = index * span;
+            let (node_proofs, round_infos, commit) = if level == 0 {
+                (
+                    vec![proofs[first].clone(), proofs[first + 1].clone()],
+                    vec![
+                        segment_info("left", first)?,
+                        segment_info("right", first + 1)?,
+                    ],
+                    commits[0].clone(),
+                )
+            } else {
+                let left =
+                    ProofGenerationInfo::load(&node_loadinfo_file(level - 1, 2 * index), report)?;
+                let right = ProofGenerationInfo::load(
+                    &node_loadinfo_file(level - 1, 2 * index + 1),
+                    report,
+                )?;
+                let mut node_proofs =
+                    ProofInfo::load_proof(&output_dir, &params_dir, &left, report)?;
+                node_proofs.append(&mut ProofInfo::load_proof(
+                    &output_dir,
+                    &params_dir,
+                    &right,
+                    report,
+                )?);
+                (
+                    node_proofs,
+                    vec![
+                        left.get_single_info("left", 0)?,
+                        right.get_single_info("right", 0)?,
+                    ],
+                    commits[1].clone(),
+                )
+            };
+
+            // nodes use the target k as the batch k so that they can be batched again
+            let mut batchinfo = BatchInfo::<E> {
+                proofs: node_proofs,
+                target_k,
+                batch_k: target_k,
+                equivalents: vec![],
+                absorb: vec![],
+                expose: vec![],
+                is_final: false,
+            };
+            batchinfo.load_commitments_check(&round_infos, commit, report)?;
+
+            let node_name = format!("{}.tree.{}.{}", proof_name, level, index);
+            let proof_piece = ProofPieceInfo::new(
+                node_name.clone(),
+                0,
+                batchinfo.get_agg_instance_size() as u32,
+                Some(format!("{}.tree.{}", proof_name, level)),
+            );
+            let (agg_proof_piece, instances, transcripts, _, _) = batchinfo.batch_proof(
+                proof_piece,
+                params_cache,
+                pkey_cache,
+                intermediate,
+                intermediate.transcript_hash,
+                None,
+                open_schema,
+                vec![],
+                None,
+                mock,
+                report,
+            )?;
+
+            agg_proof_piece.save_proof_data(
+                &vec![instances.clone()],
+                &transcripts,
+                &output_dir,
+                report,
+            )?;
+
+            let mut proof_generation_info = ProofGenerationInfo::new(
+                node_name.as_str(),
+                target_k,
+                intermediate.transcript_hash,
+                open_schema,
+            );
+            proof_generation_info.append_single_proof(agg_proof_piece);
+            proof_generation_info.record_digests(params_dir)?;
+            proof_generation_info.input_digest = Some(input_digest);
+            let loadinfo = proof_generation_info.save(output_dir, report)?;
+
+            outcome = Some(BatchOutcome {
+                loadinfo,
+                instances,
+                shadow_instances: vec![],
+                aux: None,
+                merkle_hashes: None,
+            });
+        }
+    }
+
+    if let Some(TreeNode::Node { level, index }) = node {
+        return outcome.ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "tree of {} proofs has no node {}.{}",
+                proofs.len(),
+                level,
+                index
+            ))
+        });
+    }
+
+    if node == Some(TreeNode::Final) {
+        require_valid_node(levels - 1, 0)?;
+    }
+
+    // Final round wraps the root with the requested batch k and challenge
+    let root = ProofGenerationInfo::load(&node_loadinfo_file(levels - 1, 0), report)?;
+    let mut batchinfo = BatchInfo::<E> {
+        proofs: ProofInfo::load_proof(&output_dir, &params_dir, &root, report)?,
+        target_k,
+        batch_k: k as usize,
+        equivalents: vec![],
+        absorb: vec![],
+        expose: vec![],
+        is_final: true,
+    };
+    batchinfo.load_commitments_check(
+        &vec![root.get_single_info("root", 0)?],
+        commits[2].clone(),
+        report,
+    )?;
+
+    let mut aggregate = None;
+    if batcher.auto_k {
+        let (batch_k, circuit) = batchinfo.auto_batch_k(
+            params_cache,
+            batcher.config.final_round,
+            None,
+            open_schema,
+            vec![],
+            k as usize,
+            report,
+        )?;
+        batchinfo.batch_k = batch_k;
+        aggregate = Some(circuit);
+    }
+
+    let mut proof_generation_info = ProofGenerationInfo::new(
+        format!("{}.tree.final", proof_name).as_str(),
+        batchinfo.batch_k,
+        hash,
+        open_schema,
     );
 
-    if hash == HashType::Sha || hash == HashType::Keccak {
-        let proof: Vec<ProofInfo<Bn256>> =
-            ProofInfo::load_proof(&output_dir, &params_dir, &proof_generation_info);
+    let proof_piece = ProofPieceInfo::new(
+        format!("{}.tree.final", proof_name),
+        0,
+        batchinfo.get_agg_instance_size() as u32,
+        None,
+    );
+    let (agg_proof_piece, instances, transcripts, shadow_instances, _) = batchinfo.batch_proof(
+        proof_piece,
+        params_cache,
+        pkey_cache,
+        batcher.config.final_round,
+        hash,
+        None,
+        open_schema,
+        vec![],
+        aggregate,
+        mock,
+        report,
+    )?;
 
-        println!("generate aux data for proof: {:?}", proof_generation_info);
+    agg_proof_piece.save_proof_data(&vec![instances.clone()], &transcripts, &output_dir, report)?;
 
-        // setup batch params
-        let params = load_or_build_unsafe_params::<Bn256>(
-            proof_generation_info.k as usize,
-            &params_dir.join(param_file),
+    proof_generation_info.append_single_proof(agg_proof_piece);
+    proof_generation_info.record_digests(params_dir)?;
+    let loadinfo = proof_generation_info.save(output_dir, report)?;
+
+    let shadow_instance_file = output_dir.join(format!(
+        "{}.{}.shadowinstance.data",
+        &proof_generation_info.name.clone(),
+        0
+    ));
+    store_instance(&vec![shadow_instances.clone()], &shadow_instance_file);
+    report.output(&shadow_instance_file);
+
+    let aux = if hash == HashType::Sha || hash == HashType::Keccak {
+        Some(exec_solidity_aux_gen(
             params_cache,
+            output_dir,
+            params_dir,
+            &proof_generation_info,
+            report,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(BatchOutcome {
+        loadinfo,
+        instances,
+        shadow_instances,
+        aux,
+        merkle_hashes: None,
+    })
+}
+
+/// Digest of the commits sheet of a tree node and of the proof pieces it batches, with
+/// their transcripts and instances. Re-proving a node below changes its transcript and
+/// thereby the digest of every node above it.
+fn tree_node_digest(
+    commit: &CommitmentCheck,
+    pieces: &Vec<ProofPieceInfo>,
+    output_dir: &PathBuf,
+) -> Result<String> {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(serde_json::to_vec(commit).expect("commits sheet serializes"));
+    for piece in pieces.iter() {
+        hasher.update(serde_json::to_vec(piece).expect("proof piece serializes"));
+        hasher.update(file_digest(&output_dir.join(&piece.transcript))?);
+        hasher.update(file_digest(&output_dir.join(&piece.instance))?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Whether the tree node saved in the load info file can be reused: it must be batched
+/// from the proofs of `input_digest`, match the k and hash of the tree, have the recorded
+/// digests and verify.
+fn tree_node_is_valid<E: MultiMillerLoop>(
+    batcher: &Batcher<E>,
+    loadinfo_file: &PathBuf,
+    input_digest: &String,
+    k: usize,
+    hash: HashType,
+) -> Result<bool> {
+    if !loadinfo_file.exists() {
+        return Ok(false);
+    }
+    let info = match ProofGenerationInfo::load(loadinfo_file, &batcher.report) {
+        Ok(info) => info,
+        Err(_) => return Ok(false),
+    };
+    if info.k != k || info.hashtype != hash || info.check_digests(&batcher.params_dir).is_err() {
+        println!("tree node {:?} is stale, prove it again", loadinfo_file);
+        return Ok(false);
+    }
+    if info.input_digest.as_ref() != Some(input_digest) {
+        println!(
+            "inputs of tree node {:?} changed, prove it again",
+            loadinfo_file
         );
+        return Ok(false);
+    }
+    let valid = loadinfo_verifies(
+        &info,
+        &batcher.output_dir,
+        &batcher.params_dir,
+        batcher.params_cache,
+        batcher.open_schema,
+        &batcher.report,
+    )?;
+    if !valid {
+        println!(
+            "tree node {:?} does not verify, prove it again",
+            loadinfo_file
+        );
+    }
+    Ok(valid)
+}
 
-        // the final instance size is 1
-        let params_verifier: ParamsVerifier<Bn256> = params.verifier(1).unwrap();
-
-        // generate solidity aux data
-        // it only makes sense if the transcript challenge is poseidon
-        match hash {
-            HashType::Sha => {
-                solidity_aux_gen::<_, sha2::Sha256>(
-                    &params_verifier,
-                    &proof[0].vkey,
-                    &proof[0].instances[0],
-                    proof[0].transcripts.clone(),
-                    &output_dir.join(format!(
-                        "{}.{}.aux.data",
-                        &proof_generation_info.name.clone(),
-                        0
-                    )),
-                );
-            }
-            HashType::Keccak => {
-                solidity_aux_gen::<_, sha3::Keccak256>(
-                    &params_verifier,
-                    &proof[0].vkey,
-                    &proof[0].instances[0],
-                    proof[0].transcripts.clone(),
-                    &output_dir.join(format!(
-                        "{}.{}.aux.data",
-                        &proof_generation_info.name.clone(),
-                        0
-                    )),
-                );
-            }
-            HashType::Poseidon => unreachable!(),
+/// Whether every proof of the load info can be loaded and verifies, proofs of unknown
+/// schema are verified with `open_schema`.
+pub(crate) fn loadinfo_verifies<E: MultiMillerLoop>(
+    info: &ProofGenerationInfo,
+    output_dir: &PathBuf,
+    params_dir: &PathBuf,
+    params_cache: &ParamsCache<E>,
+    open_schema: OpenSchema,
+    report: &Report,
+) -> Result<bool> {
+    let proofs: Vec<ProofInfo<E>> =
+        match ProofInfo::load_proof(output_dir, params_dir, info, report) {
+            Ok(proofs) => proofs,
+            Err(_) => return Ok(false),
+        };
+    let params = params_cache.generate_k_params(info.k, report)?;
+    for proof in proofs.iter() {
+        let params_verifier: ParamsVerifier<E> = params
+            .verifier(proof.instances.iter().map(|x| x.len()).max().unwrap_or(0))
+            .map_err(Error::halo2)?;
+        if proof
+            .verify_proof(&params_verifier, open_schema, report)
+            .is_err()
+        {
+            return Ok(false);
         }
     }
+    Ok(true)
 }
 
-pub fn exec_solidity_gen<D: Digest + Clone>(
+pub fn exec_solidity_gen<
+    E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+    D: Digest + Clone,
+>(
     params_dir: &PathBuf,
     output_dir: &PathBuf,
     k: u32,
@@ -557,24 +1614,26 @@ pub fn exec_solidity_gen<D: Digest + Clone>(
     sol_path_in: &PathBuf,
     sol_path_out: &PathBuf,
     aggregate_proof_info: &ProofGenerationInfo,
-    params_cache: &mut ParamsCache<Bn256>,
+    params_cache: &ParamsCache<E>,
     hasher: TranscriptHash,
-) {
-    let proof_params = load_or_build_unsafe_params::<Bn256>(
+    report: &Report,
+) -> Result<()> {
+    let proof_params = load_or_build_unsafe_params::<E>(
         k as usize,
         &params_dir.join(format!("K{}.params", k)),
         params_cache,
-    );
+        report,
+    )?;
 
     println!("nproof {}", n_proofs);
 
-    let proof: Vec<ProofInfo<Bn256>> =
-        ProofInfo::load_proof(&output_dir, &params_dir, aggregate_proof_info);
+    let proof: Vec<ProofInfo<E>> =
+        ProofInfo::load_proof(&output_dir, &params_dir, aggregate_proof_info, report)?;
 
     let instance_size = proof[0].instances[0].len();
 
-    let proof_params_verifier: ParamsVerifier<Bn256> =
-        proof_params.verifier(instance_size).unwrap();
+    let proof_params_verifier: ParamsVerifier<E> =
+        proof_params.verifier(instance_size).map_err(Error::halo2)?;
 
     solidity_render::<_, D>(
         &(sol_path_in.to_str().unwrap().to_owned() + "/*"),
@@ -592,4 +1651,111 @@ pub fn exec_solidity_gen<D: Digest + Clone>(
         &proof[0].instances[0],
         proof[0].transcripts.clone(),
     );
+    report.output(sol_path_out);
+
+    Ok(())
+}
+
+#[test]
+fn verify_reports_each_proof() {
+    use crate::proof::Prover;
+    use crate::samples::simple::SimpleCircuit;
+    use halo2_proofs::pairing::bn256::Bn256;
+    use halo2_proofs::pairing::bn256::Fr;
+
+    const K: usize = 13;
+    let output_dir = PathBuf::from("output/test_verify");
+    let params_dir = PathBuf::from("params");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    std::fs::create_dir_all(&params_dir).unwrap();
+    let params_cache = ParamsCache::<Bn256>::new(1, params_dir.clone());
+    let pkey_cache = ProvingKeyCache::<Bn256>::new(1, params_dir.clone());
+    let report = Report::default();
+
+    let mut loadinfo =
+        ProofGenerationInfo::new("test_verify", K, HashType::Poseidon, OpenSchema::GWC);
+    for i in 0..2 {
+        let circuit = SimpleCircuit::<Fr> {
+            a: Fr::from(100u64),
+            b: Fr::from(200u64),
+        };
+        let instances = vec![vec![Fr::from(300u64)]];
+        let piece = ProofPieceInfo::new("test_verify".to_string(), i, 1, None);
+        let transcripts = piece
+            .exec_create_proof(
+                &circuit,
+                &instances,
+                K,
+                &pkey_cache,
+                &params_cache,
+                HashType::Poseidon,
+                OpenSchema::GWC,
+                &report,
+            )
+            .unwrap();
+        piece
+            .save_proof_data(&instances, &transcripts, &output_dir, &report)
+            .unwrap();
+        loadinfo.append_single_proof(piece);
+    }
+    let config_file = loadinfo.save(&output_dir, &report).unwrap();
+    // the second proof now claims another instance
+    store_instance(
+        &vec![vec![Fr::from(301u64)]],
+        &output_dir.join(&loadinfo.proofs[1].instance),
+    );
+
+    let config_files = vec![config_file, output_dir.join("missing.loadinfo.json")];
+    let results = exec_verify::<Bn256>(
+        &params_dir,
+        &output_dir,
+        &config_files,
+        None,
+        &params_cache,
+        &report,
+    );
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].proof_idx, Some(0));
+    assert!(results[0].passed());
+    assert_eq!(results[1].proof_idx, Some(1));
+    assert!(!results[1].passed());
+    // the missing load info fails as a whole
+    assert_eq!(results[2].loadinfo, config_files[1]);
+    assert_eq!(results[2].proof_idx, None);
+    assert!(!results[2].passed());
+}
+
+#[test]
+fn tree_node_digest_tracks_inputs() {
+    let output_dir = PathBuf::from("output/test_tree_digest");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let pieces = (0..2)
+        .map(|i| ProofPieceInfo::new("segment".to_string(), i, 1, None))
+        .collect::<Vec<_>>();
+    for piece in pieces.iter() {
+        std::fs::write(output_dir.join(&piece.transcript), b"transcript").unwrap();
+        std::fs::write(output_dir.join(&piece.instance), [0u8; 32]).unwrap();
+    }
+    let commit = CommitmentCheck {
+        equivalents: vec![],
+        expose: vec![],
+        absorb: vec![],
+    };
+    let digest = tree_node_digest(&commit, &pieces, &output_dir).unwrap();
+    assert_eq!(
+        tree_node_digest(&commit, &pieces, &output_dir).unwrap(),
+        digest
+    );
+
+    // a re-proven input changes the digest
+    std::fs::write(output_dir.join(&pieces[1].transcript), b"new transcript").unwrap();
+    let changed = tree_node_digest(&commit, &pieces, &output_dir).unwrap();
+    assert_ne!(changed, digest);
+
+    // and so does another order of the inputs
+    let swapped = vec![pieces[1].clone(), pieces[0].clone()];
+    assert_ne!(
+        tree_node_digest(&commit, &swapped, &output_dir).unwrap(),
+        changed
+    );
 }
@@ -3,8 +3,20 @@
 pub mod appbuilder;
 pub mod args;
 pub mod batch;
+pub mod batcher;
+pub mod cache;
+pub mod checkpoint;
 pub mod command;
+pub mod error;
 pub mod exec;
+pub mod manifest;
+pub mod params;
+pub mod plan;
 pub mod proof;
+pub mod report;
+pub mod rows;
 pub mod samples;
 pub mod utils;
+
+pub use error::Error;
+pub use error::Result;
@@ -0,0 +1,139 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::utils::file_digest;
+use crate::utils::mapped_file_digest;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::path::PathBuf;
+
+/// How a file of the params dir was produced.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Origin {
+    /// Params generated by `Params::unsafe_setup`, only fit for tests.
+    UnsafeSetup,
+    /// Params imported from a powers of tau ceremony file.
+    Ceremony { ptau: String },
+    /// Params derived from the params of a larger k, with the origin of those params
+    /// (`None` in manifests written before it was recorded).
+    Downsized {
+        from: String,
+        #[serde(default)]
+        source: Option<Box<Origin>>,
+    },
+    /// Circuit data or standalone vkey generated by the key generation of a circuit.
+    Keygen,
+}
+
+impl Origin {
+    /// Why params of this origin are not trusted, `None` for ceremony params and params
+    /// downsized from them.
+    pub fn untrusted_reason(&self) -> Option<String> {
+        match self {
+            Origin::UnsafeSetup => Some("generated by unsafe setup".to_string()),
+            Origin::Ceremony { .. } => None,
+            Origin::Downsized { from, source } => match source {
+                Some(source) => source
+                    .untrusted_reason()
+                    .map(|reason| format!("downsized from {} {}", from, reason)),
+                None => Some(format!("downsized from {} of unknown origin", from)),
+            },
+            Origin::Keygen => Some("generated by key generation".to_string()),
+        }
+    }
+}
+
+/// Sidecar manifest `<file>.manifest.json` of a params or circuit data file.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct FileManifest {
+    pub size: u64,
+    pub sha256: String,
+    pub origin: Origin,
+}
+
+impl FileManifest {
+    pub fn path(file: &Path) -> PathBuf {
+        let mut name = file.as_os_str().to_os_string();
+        name.push(".manifest.json");
+        PathBuf::from(name)
+    }
+
+    /// Computes and saves the manifest of the file.
+    pub fn record(file: &Path, origin: Origin) -> Result<Self> {
+        let size = std::fs::metadata(file).map_err(Error::io(file))?.len();
+        let manifest = FileManifest {
+            size,
+            sha256: file_digest(file)?,
+            origin,
+        };
+        let manifest_file = Self::path(file);
+        log::info!("write manifest {:?}", manifest_file);
+        let fd = std::fs::File::create(&manifest_file).map_err(Error::io(&manifest_file))?;
+        serde_json::to_writer_pretty(fd, &manifest).map_err(Error::serialize(&manifest_file))?;
+        Ok(manifest)
+    }
+
+    /// Loads the manifest of the file, `None` for files without manifest.
+    pub fn load(file: &Path) -> Result<Option<Self>> {
+        let manifest_file = Self::path(file);
+        if !manifest_file.exists() {
+            return Ok(None);
+        }
+        let fd = std::fs::File::open(&manifest_file).map_err(Error::io(&manifest_file))?;
+        serde_json::from_reader(fd)
+            .map(Some)
+            .map_err(Error::parse(&manifest_file))
+    }
+
+    /// Checks the size (cheap, catches truncated downloads) and then the digest of the file.
+    pub fn check(&self, file: &Path) -> Result<()> {
+        self.check_with(file, || file_digest(file))
+    }
+
+    fn check_with(&self, file: &Path, digest: impl FnOnce() -> Result<String>) -> Result<()> {
+        let size = std::fs::metadata(file).map_err(Error::io(file))?.len();
+        if size != self.size {
+            return Err(Error::SizeMismatch {
+                path: file.to_path_buf(),
+                expected: self.size,
+                found: size,
+            });
+        }
+        let found = digest()?;
+        if found != self.sha256 {
+            return Err(Error::DigestMismatch {
+                path: file.to_path_buf(),
+                expected: self.sha256.clone(),
+                found,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks the file against its manifest before it is loaded. Files without manifest,
+/// e.g. of older params dirs, are loaded unchecked.
+pub fn verify_file(file: &Path) -> Result<Option<Origin>> {
+    verify_file_with(file, || file_digest(file))
+}
+
+/// `verify_file` of a memory mapped file, digesting the mapped bytes.
+pub fn verify_mapped_file(file: &Path, bytes: &[u8]) -> Result<Option<Origin>> {
+    verify_file_with(file, || mapped_file_digest(file, bytes))
+}
+
+fn verify_file_with(
+    file: &Path,
+    digest: impl FnOnce() -> Result<String>,
+) -> Result<Option<Origin>> {
+    match FileManifest::load(file)? {
+        Some(manifest) => {
+            manifest.check_with(file, digest)?;
+            Ok(Some(manifest.origin))
+        }
+        None => {
+            log::warn!("no manifest for {:?}, load it unchecked", file);
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::Error;
+use crate::error::Result;
+use halo2_proofs::arithmetic::best_fft;
+use halo2_proofs::arithmetic::parallelize;
+use halo2_proofs::arithmetic::CurveAffine;
+use halo2_proofs::arithmetic::Field;
+use halo2_proofs::pairing::bn256::Fq;
+use halo2_proofs::pairing::bn256::Fq2;
+use halo2_proofs::pairing::bn256::G1Affine;
+use halo2_proofs::pairing::bn256::G2Affine;
+use halo2_proofs::pairing::group::prime::PrimeCurveAffine;
+use halo2_proofs::pairing::group::Curve;
+use halo2_proofs::pairing::group::GroupEncoding;
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::poly::EvaluationDomain;
+use std::collections::HashMap;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+/// Modulus of the bn254 base field, the only curve of ptau files we can import.
+const BN254_Q: &str = "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47";
+
+const PTAU_HEADER: u32 = 1;
+const PTAU_TAU_G1: u32 = 2;
+const PTAU_TAU_G2: u32 = 3;
+
+fn invalid_ptau(path: &Path, msg: String) -> Error {
+    Error::IoError {
+        path: path.to_path_buf(),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, msg),
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Field elements of ptau files are little endian in montgomery form.
+fn fq_from_montgomery(bytes: &[u8], r_inv: &Fq) -> Option<Fq> {
+    let mut repr = [0u8; 32];
+    repr.copy_from_slice(bytes);
+    Option::<Fq>::from(Fq::from_bytes(&repr)).map(|x| x * r_inv)
+}
+
+/// Reads the first `2^k` powers of tau in G1 and `[tau]G2` from a snarkjs ptau file
+/// (e.g. of the perpetual powers of tau ceremony).
+fn read_ptau(ptau: &Path, k: u32) -> Result<(Vec<G1Affine>, G2Affine)> {
+    let mut fd = std::fs::File::open(ptau).map_err(Error::io(ptau))?;
+
+    let mut magic = [0u8; 4];
+    fd.read_exact(&mut magic).map_err(Error::io(ptau))?;
+    if &magic != b"ptau" {
+        return Err(invalid_ptau(ptau, "not a ptau file".to_string()));
+    }
+    let _version = read_u32(&mut fd).map_err(Error::io(ptau))?;
+    let n_sections = read_u32(&mut fd).map_err(Error::io(ptau))?;
+
+    let mut sections = HashMap::new();
+    for _ in 0..n_sections {
+        let section_type = read_u32(&mut fd).map_err(Error::io(ptau))?;
+        let size = read_u64(&mut fd).map_err(Error::io(ptau))?;
+        let offset = fd.stream_position().map_err(Error::io(ptau))?;
+        sections.insert(section_type, offset);
+        fd.seek(SeekFrom::Current(size as i64))
+            .map_err(Error::io(ptau))?;
+    }
+    let seek_section = |fd: &mut std::fs::File, section_type: u32| -> Result<()> {
+        let offset = sections
+            .get(&section_type)
+            .ok_or_else(|| invalid_ptau(ptau, format!("section {} is missing", section_type)))?;
+        fd.seek(SeekFrom::Start(*offset)).map_err(Error::io(ptau))?;
+        Ok(())
+    };
+
+    seek_section(&mut fd, PTAU_HEADER)?;
+    let n8 = read_u32(&mut fd).map_err(Error::io(ptau))? as usize;
+    let mut q = vec![0u8; n8];
+    fd.read_exact(&mut q).map_err(Error::io(ptau))?;
+    q.reverse();
+    if hex::encode(&q) != BN254_Q {
+        return Err(invalid_ptau(ptau, "curve is not bn254".to_string()));
+    }
+    let power = read_u32(&mut fd).map_err(Error::io(ptau))?;
+    if power < k {
+        return Err(invalid_ptau(
+            ptau,
+            format!("ptau of power {} can not provide params of k {}", power, k),
+        ));
+    }
+
+    let r_inv = Fq::from(2).pow_vartime(&[256]).invert().unwrap();
+    let point_error = |i: usize| invalid_ptau(ptau, format!("invalid point {}", i));
+
+    seek_section(&mut fd, PTAU_TAU_G1)?;
+    let mut g1_bytes = vec![0u8; (1 << k) * 64];
+    fd.read_exact(&mut g1_bytes).map_err(Error::io(ptau))?;
+    let g = g1_bytes
+        .chunks(64)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let x = fq_from_montgomery(&bytes[0..32], &r_inv).ok_or_else(|| point_error(i))?;
+            let y = fq_from_montgomery(&bytes[32..64], &r_inv).ok_or_else(|| point_error(i))?;
+            Option::<G1Affine>::from(G1Affine::from_xy(x, y)).ok_or_else(|| point_error(i))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    seek_section(&mut fd, PTAU_TAU_G2)?;
+    let mut g2_bytes = vec![0u8; 2 * 128];
+    fd.read_exact(&mut g2_bytes).map_err(Error::io(ptau))?;
+    let g2 = g2_bytes
+        .chunks(128)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let coordinate = |offset: usize| -> Result<Fq2> {
+                Ok(Fq2 {
+                    c0: fq_from_montgomery(&bytes[offset..offset + 32], &r_inv)
+                        .ok_or_else(|| point_error(i))?,
+                    c1: fq_from_montgomery(&bytes[offset + 32..offset + 64], &r_inv)
+                        .ok_or_else(|| point_error(i))?,
+                })
+            };
+            Option::<G2Affine>::from(G2Affine::from_xy(coordinate(0)?, coordinate(64)?))
+                .ok_or_else(|| point_error(i))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // the verifier of halo2 takes the generators as [1]G1 and [1]G2
+    if g[0] != G1Affine::generator() || g2[0] != G2Affine::generator() {
+        return Err(invalid_ptau(
+            ptau,
+            "ceremony does not start from the bn254 generators".to_string(),
+        ));
+    }
+    Ok((g, g2[1]))
+}
+
+/// Builds params from the powers of tau in G1, computing their lagrange basis the same
+/// way `Params::unsafe_setup` does.
+fn params_from_powers<C: CurveAffine>(k: u32, g: Vec<C>, additional_data: Vec<u8>) -> Params<C> {
+    let n = 1u64 << k;
+    let mut g_lagrange_projective = g.iter().map(|x| x.to_curve()).collect::<Vec<_>>();
+    let domain = EvaluationDomain::<C::Scalar>::new(1, k);
+    best_fft(&mut g_lagrange_projective, domain.get_omega_inv(), k);
+    let n_inv = C::Scalar::from(n).invert().unwrap();
+    parallelize(&mut g_lagrange_projective, |g, _| {
+        for g in g.iter_mut() {
+            *g = *g * n_inv;
+        }
+    });
+    let mut g_lagrange = vec![C::identity(); n as usize];
+    C::Curve::batch_normalize(&g_lagrange_projective, &mut g_lagrange);
+
+    Params {
+        k,
+        n,
+        g,
+        g_lagrange,
+        additional_data,
+    }
+}
+
+/// Converts the ceremony of a ptau file into params of size k.
+pub fn import_ptau(ptau: &Path, k: u32) -> Result<Params<G1Affine>> {
+    let (g, s_g2) = read_ptau(ptau, k)?;
+    Ok(params_from_powers(
+        k,
+        g,
+        Vec::from(s_g2.to_bytes().as_ref()),
+    ))
+}
+
+/// Derives params of a smaller k from the same SRS.
+pub fn downsize_params<C: CurveAffine>(params: &Params<C>, k: u32) -> Result<Params<C>> {
+    if k > params.k {
+        return Err(Error::InvalidArgument(format!(
+            "can not downsize params of k {} to k {}",
+            params.k, k
+        )));
+    }
+    Ok(params_from_powers(
+        k,
+        params.g[..1 << k].to_vec(),
+        params.additional_data.clone(),
+    ))
+}
+
+#[cfg(test)]
+fn write_test_ptau(ptau: &Path, power: u32, tau: halo2_proofs::pairing::bn256::Fr) {
+    use std::io::Write;
+
+    let r = Fq::from(2).pow_vartime(&[256]);
+    let montgomery = |x: &Fq| (*x * r).to_bytes();
+    let section = |out: &mut Vec<u8>, section_type: u32, body: Vec<u8>| {
+        out.extend_from_slice(&section_type.to_le_bytes());
+        out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        out.extend_from_slice(&body);
+    };
+
+    let mut header = vec![];
+    header.extend_from_slice(&32u32.to_le_bytes());
+    let mut q = hex::decode(BN254_Q).unwrap();
+    q.reverse();
+    header.extend_from_slice(&q);
+    header.extend_from_slice(&power.to_le_bytes());
+    header.extend_from_slice(&power.to_le_bytes());
+
+    let mut tau_g1 = vec![];
+    let mut tau_i = halo2_proofs::pairing::bn256::Fr::one();
+    for _ in 0..(1 << power) {
+        let point = (G1Affine::generator() * tau_i).to_affine();
+        tau_g1.extend_from_slice(&montgomery(&point.x));
+        tau_g1.extend_from_slice(&montgomery(&point.y));
+        tau_i = tau_i * tau;
+    }
+
+    let mut tau_g2 = vec![];
+    for point in [
+        G2Affine::generator(),
+        (G2Affine::generator() * tau).to_affine(),
+    ] {
+        for coordinate in [point.x, point.y] {
+            tau_g2.extend_from_slice(&montgomery(&coordinate.c0));
+            tau_g2.extend_from_slice(&montgomery(&coordinate.c1));
+        }
+    }
+
+    let mut out = b"ptau".to_vec();
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&3u32.to_le_bytes());
+    section(&mut out, PTAU_HEADER, header);
+    section(&mut out, PTAU_TAU_G1, tau_g1);
+    section(&mut out, PTAU_TAU_G2, tau_g2);
+    std::fs::File::create(ptau)
+        .unwrap()
+        .write_all(&out)
+        .unwrap();
+}
+
+#[test]
+fn import_ptau_fixture() {
+    use halo2_proofs::pairing::bn256::Fr;
+
+    let output_dir = Path::new("output");
+    std::fs::create_dir_all(output_dir).unwrap();
+    let ptau = output_dir.join("test_power5.ptau");
+    let tau = Fr::from(7u64);
+    write_test_ptau(&ptau, 5, tau);
+
+    let params = import_ptau(&ptau, 4).unwrap();
+    assert_eq!(params.k, 4);
+    assert_eq!(params.g.len(), 16);
+    let mut tau_i = Fr::one();
+    for g in params.g.iter() {
+        assert_eq!(*g, (G1Affine::generator() * tau_i).to_affine());
+        tau_i = tau_i * tau;
+    }
+    let s_g2 = (G2Affine::generator() * tau).to_affine();
+    assert_eq!(params.additional_data, s_g2.to_bytes().as_ref().to_vec());
+
+    assert!(import_ptau(&ptau, 6).is_err());
+}
+
+#[test]
+fn downsize_params_matches_params_of_same_tau() {
+    use halo2_proofs::pairing::bn256::Fr;
+
+    let tau = Fr::from(7u64);
+    let powers = |k: u32| {
+        let mut tau_i = Fr::one();
+        (0..1 << k)
+            .map(|_| {
+                let point = (G1Affine::generator() * tau_i).to_affine();
+                tau_i = tau_i * tau;
+                point
+            })
+            .collect::<Vec<_>>()
+    };
+    let large = params_from_powers(6, powers(6), vec![]);
+    let downsized = downsize_params(&large, 4).unwrap();
+    let small = params_from_powers(4, powers(4), vec![]);
+    assert_eq!(downsized.k, 4);
+    assert_eq!(downsized.n, 16);
+    assert_eq!(downsized.g, small.g);
+    assert_eq!(downsized.g_lagrange, small.g_lagrange);
+
+    // the i-th lagrange point is [L_i(tau)]G1 with L_i(x) = w^i (x^n - 1) / (n (x - w^i))
+    let n = Fr::from(16u64);
+    let omega = EvaluationDomain::<Fr>::new(1, 4).get_omega();
+    let mut omega_i = Fr::one();
+    for g in downsized.g_lagrange.iter() {
+        let l_i = omega_i
+            * (tau.pow_vartime(&[16]) - Fr::one())
+            * (n * (tau - omega_i)).invert().unwrap();
+        assert_eq!(*g, (G1Affine::generator() * l_i).to_affine());
+        omega_i = omega_i * omega;
+    }
+
+    assert!(downsize_params(&small, 6).is_err());
+}
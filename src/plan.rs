@@ -0,0 +1,316 @@
+use crate::args::Accumulator;
+use crate::args::HashType;
+use crate::args::OpenSchema;
+use crate::batch::CommitmentCheck;
+use crate::batcher::Batcher;
+use crate::error::Error;
+use crate::error::Result;
+use crate::exec::loadinfo_verifies;
+use crate::proof::ParamsCache;
+use crate::proof::ProofGenerationInfo;
+use crate::proof::ProvingKeyCache;
+use crate::report::Report;
+use crate::utils::file_digest;
+use halo2_proofs::arithmetic::MultiMillerLoop;
+use halo2_proofs::arithmetic::MultiMillerLoopOnProvePairing;
+use halo2aggregator_s::circuit_verifier::G2AffineBaseHelper;
+use halo2aggregator_s::circuit_verifier::GtHelper;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+fn default_hash() -> HashType {
+    HashType::Poseidon
+}
+
+fn default_open_schema() -> OpenSchema {
+    OpenSchema::Shplonk
+}
+
+fn default_accumulator() -> Accumulator {
+    Accumulator::UseCommitment
+}
+
+fn default_use_ecc_select_chip() -> bool {
+    true
+}
+
+/// A single `batch` invocation of a plan.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchStep {
+    pub name: String,
+    /// Either the name of another step of the plan, standing for its batch proof,
+    /// or the path of a load info file.
+    pub inputs: Vec<String>,
+    pub commits: Vec<PathBuf>,
+    pub k: u32,
+    #[serde(default = "default_hash")]
+    pub hash: HashType,
+    #[serde(default = "default_open_schema")]
+    pub open_schema: OpenSchema,
+    #[serde(default = "default_use_ecc_select_chip")]
+    pub use_ecc_select_chip: bool,
+    #[serde(default = "default_accumulator")]
+    pub accumulator: Accumulator,
+}
+
+/// A DAG of batch steps, e.g. a layered batch where `batch2` batches the proof of `batch1`:
+///
+/// ```json
+/// { "steps": [
+///     { "name": "batch2", "inputs": ["batch1", "output/test2.loadinfo.json"], "commits": ["sample/batchinfo2.json"], "k": 22 },
+///     { "name": "batch1", "inputs": ["output/test1.loadinfo.json"], "commits": ["sample/batchinfo1.json"], "k": 22 }
+/// ] }
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchPlan {
+    pub steps: Vec<BatchStep>,
+}
+
+impl BatchPlan {
+    pub fn load(planfile: &Path, report: &Report) -> Result<Self> {
+        let fd = std::fs::File::open(planfile).map_err(Error::io(planfile))?;
+        log::info!("read batch plan {:?}", planfile);
+        report.input(planfile);
+        serde_json::from_reader(fd).map_err(Error::parse(planfile))
+    }
+
+    fn step(&self, name: &str) -> Option<&BatchStep> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+
+    /// Steps ordered so that each step follows the steps it takes as input.
+    pub fn order(&self) -> Result<Vec<&BatchStep>> {
+        let mut ordered: Vec<&BatchStep> = vec![];
+        let mut visiting: Vec<&str> = vec![];
+
+        fn visit<'a>(
+            plan: &'a BatchPlan,
+            step: &'a BatchStep,
+            visiting: &mut Vec<&'a str>,
+            ordered: &mut Vec<&'a BatchStep>,
+        ) -> Result<()> {
+            if ordered.iter().any(|x| x.name == step.name) {
+                return Ok(());
+            }
+            if visiting.contains(&step.name.as_str()) {
+                return Err(Error::InvalidArgument(format!(
+                    "batch plan has a cycle through step {}",
+                    step.name
+                )));
+            }
+            visiting.push(&step.name);
+            for input in step.inputs.iter() {
+                if let Some(dep) = plan.step(input) {
+                    visit(plan, dep, visiting, ordered)?;
+                }
+            }
+            visiting.pop();
+            ordered.push(step);
+            Ok(())
+        }
+
+        for step in self.steps.iter() {
+            if self.steps.iter().filter(|x| x.name == step.name).count() > 1 {
+                return Err(Error::InvalidArgument(format!(
+                    "batch plan has duplicated step {}",
+                    step.name
+                )));
+            }
+            visit(self, step, &mut visiting, &mut ordered)?;
+        }
+        Ok(ordered)
+    }
+
+    /// Runs the steps in order and returns the load info of each step. Steps whose
+    /// batch proof is already in the output dir, was created from the same step and
+    /// inputs, and verifies are skipped.
+    pub fn run<
+        E: MultiMillerLoop + G2AffineBaseHelper + GtHelper + MultiMillerLoopOnProvePairing,
+    >(
+        &self,
+        params_dir: &PathBuf,
+        output_dir: &PathBuf,
+        params_cache: &ParamsCache<E>,
+        pkey_cache: &ProvingKeyCache<E>,
+        report: &Report,
+    ) -> Result<Vec<(String, PathBuf)>> {
+        let mut loadinfos: HashMap<String, PathBuf> = HashMap::new();
+        let mut outputs = vec![];
+
+        for step in self.order()? {
+            let mut loadinfo = output_dir.join(format!("{}.loadinfo.json", step.name));
+            let config_files = step
+                .inputs
+                .iter()
+                .map(|input| {
+                    loadinfos
+                        .get(input)
+                        .cloned()
+                        .unwrap_or_else(|| PathBuf::from(input))
+                })
+                .collect::<Vec<_>>();
+            let digest = step_digest(step, &config_files, output_dir, report)?;
+            if step_is_done(
+                step,
+                &digest,
+                &loadinfo,
+                output_dir,
+                params_dir,
+                params_cache,
+                report,
+            )? {
+                println!("skip batch step {}, {:?} is valid", step.name, loadinfo);
+            } else {
+                println!("run batch step {}", step.name);
+                let commits = step
+                    .commits
+                    .iter()
+                    .map(|x| CommitmentCheck::load(x.as_path(), report))
+                    .collect::<Result<Vec<_>>>()?;
+                let outcome = Batcher::new(
+                    params_dir.clone(),
+                    output_dir.clone(),
+                    params_cache,
+                    pkey_cache,
+                )
+                .k(step.k)
+                .hash(step.hash)
+                .open_schema(step.open_schema)
+                .accumulator(step.accumulator)
+                .use_ecc_select_chip(step.use_ecc_select_chip)
+                .report(report.clone())
+                .batch(&step.name, &config_files, commits)?;
+                loadinfo = outcome.loadinfo;
+                let mut info = ProofGenerationInfo::load(&loadinfo, report)?;
+                info.step_digest = Some(digest);
+                info.save(output_dir, report)?;
+            }
+            loadinfos.insert(step.name.clone(), loadinfo.clone());
+            outputs.push((step.name.clone(), loadinfo));
+        }
+        Ok(outputs)
+    }
+}
+
+/// Digest of the step and of the files it reads: its commitment sheets, its input load
+/// infos and their transcripts. A rerun input step changes the digest of the steps
+/// batching it.
+fn step_digest(
+    step: &BatchStep,
+    config_files: &Vec<PathBuf>,
+    output_dir: &Path,
+    report: &Report,
+) -> Result<String> {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(serde_json::to_vec(step).expect("batch step serializes"));
+    for commits in step.commits.iter() {
+        hasher.update(file_digest(commits)?);
+    }
+    for config_file in config_files.iter() {
+        hasher.update(file_digest(config_file)?);
+        for proof in ProofGenerationInfo::load(config_file, report)?
+            .proofs
+            .iter()
+        {
+            let transcript = output_dir.join(&proof.transcript);
+            if transcript.exists() {
+                hasher.update(file_digest(&transcript)?);
+            }
+        }
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Whether the batch proof of the step is present, was created from the step and inputs
+/// of `digest`, matches its k and hash, and verifies.
+fn step_is_done<E: MultiMillerLoop>(
+    step: &BatchStep,
+    digest: &String,
+    loadinfo: &PathBuf,
+    output_dir: &PathBuf,
+    params_dir: &PathBuf,
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Result<bool> {
+    if !loadinfo.exists() {
+        return Ok(false);
+    }
+    let info = match ProofGenerationInfo::load(loadinfo, report) {
+        Ok(info) => info,
+        Err(_) => return Ok(false),
+    };
+    if info.k != step.k as usize || info.hashtype != step.hash {
+        return Ok(false);
+    }
+    if info.step_digest.as_ref() != Some(digest) {
+        println!("batch step {} or its inputs changed", step.name);
+        return Ok(false);
+    }
+    loadinfo_verifies(
+        &info,
+        output_dir,
+        params_dir,
+        params_cache,
+        step.open_schema,
+        report,
+    )
+}
+
+#[cfg(test)]
+fn test_plan(steps: &[(&str, &[&str])]) -> BatchPlan {
+    BatchPlan {
+        steps: steps
+            .iter()
+            .map(|(name, inputs)| BatchStep {
+                name: name.to_string(),
+                inputs: inputs.iter().map(|x| x.to_string()).collect(),
+                commits: vec![],
+                k: 22,
+                hash: default_hash(),
+                open_schema: default_open_schema(),
+                use_ecc_select_chip: default_use_ecc_select_chip(),
+                accumulator: default_accumulator(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn plan_order_follows_inputs() {
+    let plan = test_plan(&[
+        ("batch3", &["batch2", "batch1"]),
+        ("batch2", &["batch1", "output/test2.loadinfo.json"]),
+        ("batch1", &["output/test1.loadinfo.json"]),
+    ]);
+    let names = plan
+        .order()
+        .unwrap()
+        .iter()
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["batch1", "batch2", "batch3"]);
+}
+
+#[test]
+fn plan_order_rejects_cycle() {
+    let plan = test_plan(&[
+        ("batch1", &["batch3"]),
+        ("batch2", &["batch1"]),
+        ("batch3", &["batch2"]),
+    ]);
+    assert!(matches!(plan.order(), Err(Error::InvalidArgument(_))));
+    let plan = test_plan(&[("batch1", &["batch1"])]);
+    assert!(matches!(plan.order(), Err(Error::InvalidArgument(_))));
+}
+
+#[test]
+fn plan_order_rejects_duplicated_step() {
+    let plan = test_plan(&[
+        ("batch1", &["output/test1.loadinfo.json"]),
+        ("batch1", &["output/test2.loadinfo.json"]),
+    ]);
+    assert!(matches!(plan.order(), Err(Error::InvalidArgument(_))));
+}
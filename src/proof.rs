@@ -1,5 +1,19 @@
 use crate::args::HashType;
 use crate::args::OpenSchema;
+use crate::cache::CacheStats;
+use crate::cache::SharedCache;
+use crate::error::ensure_exists;
+use crate::error::Error;
+use crate::error::Result;
+use crate::manifest::verify_file;
+use crate::manifest::verify_mapped_file;
+use crate::manifest::FileManifest;
+use crate::manifest::Origin;
+use crate::report::Report;
+use crate::utils::file_digest;
+use crate::utils::file_size;
+use crate::utils::write_file_atomic;
+use ff::PrimeField;
 use halo2_proofs::arithmetic::FieldExt;
 use halo2_proofs::arithmetic::MultiMillerLoop;
 use halo2_proofs::dev::MockProver;
@@ -24,96 +38,159 @@ use halo2aggregator_s::transcript::poseidon::PoseidonRead;
 use halo2aggregator_s::transcript::poseidon::PoseidonWrite;
 use halo2aggregator_s::transcript::sha256::ShaRead;
 use halo2aggregator_s::transcript::sha256::ShaWrite;
-use lru::LruCache;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+/// Proving keys of the circuit data files in `cache_dir`, shareable between concurrent
+/// batch jobs.
 pub struct ProvingKeyCache<E: MultiMillerLoop> {
-    pub cache: LruCache<String, ProvingKey<E::G1Affine>>,
+    pub cache: SharedCache<ProvingKey<E::G1Affine>>,
     pub cache_dir: PathBuf,
+    /// Reads circuit data files through a memory map, see `read_pk_mmap`.
+    pub mmap: bool,
 }
 
 impl<E: MultiMillerLoop> ProvingKeyCache<E> {
     pub fn new(cache_size: usize, cache_dir: PathBuf) -> Self {
-        let lrucache = LruCache::<String, ProvingKey<E::G1Affine>>::new(
-            NonZeroUsize::new(cache_size).unwrap(),
-        );
         ProvingKeyCache {
-            cache: lrucache,
+            cache: SharedCache::new(cache_size),
             cache_dir,
+            mmap: false,
         }
     }
 
-    pub fn contains(&mut self, key: &String) -> bool {
-        self.cache.get(key).is_some()
+    /// Cache bounded by the estimated bytes of its proving keys, which are the sizes of
+    /// their circuit data files. The keys in memory are several times larger, as they also
+    /// hold the polynomials and extended cosets computed from the circuit data.
+    pub fn with_max_bytes(max_bytes: u64, cache_dir: PathBuf) -> Self {
+        ProvingKeyCache {
+            cache: SharedCache::with_max_bytes(max_bytes),
+            cache_dir,
+            mmap: false,
+        }
     }
 
-    pub fn push<'a>(
-        &'a mut self,
-        key: String,
-        v: ProvingKey<E::G1Affine>,
-    ) -> &'a ProvingKey<E::G1Affine> {
-        self.cache.push(key.clone(), v);
-        self.cache.get(&key).unwrap()
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
     }
 
-    pub fn load_or_build_pkey<'a, C: Circuit<E::Scalar>>(
-        &'a mut self,
+    pub fn contains(&self, key: &String) -> bool {
+        self.cache.contains(key)
+    }
+
+    pub fn load_or_build_pkey<C: Circuit<E::Scalar>>(
+        &self,
         c: &C,
         params: &Params<E::G1Affine>,
         name: String,
-    ) -> &'a ProvingKey<E::G1Affine> {
+        report: &Report,
+    ) -> Result<Arc<ProvingKey<E::G1Affine>>> {
         load_or_build_pkey::<E, C>(
             &params,
             c,
             &self.cache_dir.join(name.clone()),
-            &self.cache_dir.join(format!("{}.vkey.data", name.clone())),
+            &self.cache_dir.join(vkey_file_name(&name)),
             self,
+            report,
         )
     }
+
+    /// Loads the proving key from existing circuit data, for callers that do not hold the circuit.
+    pub fn load_pkey(
+        &self,
+        params: &Params<E::G1Affine>,
+        name: String,
+        report: &Report,
+    ) -> Result<Arc<ProvingKey<E::G1Affine>>> {
+        let cache_file = self.cache_dir.join(name);
+        let key = cache_file.to_string_lossy().to_string();
+        self.cache.get_or_load(&key, || {
+            let _phase = report.phase("pkey load", cache_file.display());
+            Ok((
+                read_pk::<E>(params, &cache_file, self.mmap, report)?,
+                file_size(&cache_file)?,
+            ))
+        })
+    }
 }
 
+/// Params of the params files in `cache_dir`, shareable between concurrent batch jobs.
 pub struct ParamsCache<E: MultiMillerLoop> {
-    pub cache: LruCache<String, Params<E::G1Affine>>,
+    pub cache: SharedCache<Params<E::G1Affine>>,
     pub cache_dir: PathBuf,
+    /// Fails on missing params instead of generating them with `Params::unsafe_setup`.
+    pub require_trusted_params: bool,
 }
 
 impl<E: MultiMillerLoop> ParamsCache<E> {
     pub fn new(cache_size: usize, cache_dir: PathBuf) -> Self {
-        let lrucache =
-            LruCache::<String, Params<E::G1Affine>>::new(NonZeroUsize::new(cache_size).unwrap());
         ParamsCache {
-            cache: lrucache,
+            cache: SharedCache::new(cache_size),
             cache_dir,
+            require_trusted_params: false,
         }
     }
-    pub fn contains(&mut self, key: &String) -> bool {
-        self.cache.get(key).is_some()
+    pub fn contains(&self, key: &String) -> bool {
+        self.cache.contains(key)
     }
-    pub fn push<'a>(&'a mut self, key: String, v: Params<E::G1Affine>) -> &'a Params<E::G1Affine> {
-        self.cache.push(key.clone(), v);
-        self.cache.get(&key).unwrap()
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
     }
-    pub fn generate_k_params(&mut self, k: usize) -> &Params<E::G1Affine> {
+    pub fn generate_k_params(&self, k: usize, report: &Report) -> Result<Arc<Params<E::G1Affine>>> {
         let params_path = &self.cache_dir.join(format!("K{}.params", k));
-        load_or_build_unsafe_params::<E>(k, params_path, self)
+        load_or_build_unsafe_params::<E>(k, params_path, self, report)
     }
 }
 
+/// Accepts the per column instance sizes as well as the single size of older load infos.
+fn deserialize_instance_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum InstanceSize {
+        Single(u32),
+        Columns(Vec<u32>),
+    }
+    Ok(match InstanceSize::deserialize(deserializer)? {
+        InstanceSize::Single(size) => vec![size],
+        InstanceSize::Columns(sizes) => sizes,
+    })
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ProofPieceInfo {
     pub circuit: String,
-    pub instance_size: u32,
+    /// Size of each instance column.
+    #[serde(deserialize_with = "deserialize_instance_size")]
+    pub instance_size: Vec<u32>,
     pub witness: String,
     pub instance: String,
     pub transcript: String,
+    /// Digest of the circuit data file, see `file_digest`.
+    #[serde(default, alias = "vkey_digest")]
+    pub circuit_digest: Option<String>,
+    /// Standalone vkey file, see `vkey_file_name`. Verifiers only need this file instead
+    /// of the whole circuit data.
+    #[serde(default)]
+    pub vkey: Option<String>,
+    /// Digest of the standalone vkey file, see `file_digest`.
+    #[serde(default)]
+    pub vkey_file_digest: Option<String>,
+}
+
+/// Name of the standalone vkey file of the circuit data file.
+pub fn vkey_file_name(circuit: &str) -> String {
+    let prefix = circuit.strip_suffix(".circuit.data").unwrap_or(circuit);
+    format!("{}.vkey.data", prefix)
 }
 
 impl ProofPieceInfo {
+    /// Piece of a circuit with a single instance column, set `instance_size` for more columns.
     pub fn new(
         name: String,
         i: usize,
@@ -126,7 +203,47 @@ impl ProofPieceInfo {
             instance: format!("{}.{}.instance.data", name, i),
             transcript: format!("{}.{}.transcript.data", name, i),
             circuit: format!("{}.circuit.data", circuit_prefix),
-            instance_size,
+            instance_size: vec![instance_size],
+            circuit_digest: None,
+            vkey: None,
+            vkey_file_digest: None,
+        }
+    }
+
+    /// Standalone vkey file of the piece: the one referenced by the load info, or the one
+    /// written next to the circuit data by keygen or `export-vkey` if it exists.
+    pub fn vkey_file(&self, param_folder: &Path) -> Option<PathBuf> {
+        match &self.vkey {
+            Some(vkey) => Some(param_folder.join(vkey)),
+            None => Some(param_folder.join(vkey_file_name(&self.circuit))).filter(|x| x.exists()),
+        }
+    }
+
+    /// Checks that the instance file holds `instance_size` field elements, as
+    /// `load_instance` panics on missing or truncated instance files.
+    pub fn check_instance_file<F: PrimeField>(&self, instance_file: &Path) -> Result<()> {
+        let found = file_size(instance_file)?;
+        let expected = self.instance_size.iter().map(|x| *x as u64).sum::<u64>()
+            * F::Repr::default().as_ref().len() as u64;
+        if found != expected {
+            return Err(Error::SizeMismatch {
+                path: instance_file.to_path_buf(),
+                expected,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads the vkey of the piece, preferring its standalone vkey file over the circuit data.
+    pub fn read_vkey<E: MultiMillerLoop>(
+        &self,
+        param_folder: &Path,
+        report: &Report,
+    ) -> Result<VerifyingKey<E::G1Affine>> {
+        match self.vkey_file(param_folder) {
+            Some(vkey_file) => read_vkey_full::<E>(&vkey_file, report),
+            None => read_vkey_full::<E>(&param_folder.join(&self.circuit), report),
         }
     }
 }
@@ -138,40 +255,150 @@ pub struct ProofGenerationInfo {
     pub param: String,
     pub name: String,
     pub hashtype: HashType,
+    /// Open schema the proofs were created with, unknown for older load infos.
+    #[serde(default)]
+    pub open_schema: Option<OpenSchema>,
+    /// Digest of the params file, see `file_digest`.
+    #[serde(default)]
+    pub params_digest: Option<String>,
+    /// Digest of the plan step and inputs the batch proof was created from, see `step_digest`.
+    #[serde(default)]
+    pub step_digest: Option<String>,
+    /// Digest of the commits sheet and proofs a tree node batches, see `tree_node_digest`.
+    #[serde(default)]
+    pub input_digest: Option<String>,
 }
 
 impl ProofGenerationInfo {
-    pub fn new(name: &str, k: usize, hashtype: HashType) -> Self {
+    pub fn new(name: &str, k: usize, hashtype: HashType, open_schema: OpenSchema) -> Self {
         ProofGenerationInfo {
             name: name.to_string(),
             k,
             proofs: vec![],
             param: format!("K{}.params", k),
             hashtype,
+            open_schema: Some(open_schema),
+            params_digest: None,
+            step_digest: None,
+            input_digest: None,
         }
     }
     pub fn append_single_proof(&mut self, pi: ProofPieceInfo) {
         self.proofs.push(pi)
     }
-    pub fn save(&self, cache_folder: &Path) {
+    /// Writes `<name>.loadinfo.json` into the folder and returns its path.
+    pub fn save(&self, cache_folder: &Path, report: &Report) -> Result<PathBuf> {
         let cache_file = cache_folder.join(format!("{}.loadinfo.json", &self.name));
-        let json = serde_json::to_string_pretty(self).unwrap();
+        let json = serde_json::to_string_pretty(self).map_err(Error::serialize(&cache_file))?;
         log::info!("write proof load info {:?}", cache_file);
-        let mut fd = std::fs::File::create(&cache_file).unwrap();
-        fd.write(json.as_bytes()).unwrap();
+        // the rec load info of a continuation is rewritten after each round
+        write_file_atomic(&cache_file, json.as_bytes())?;
+        report.output(&cache_file);
+        Ok(cache_file)
     }
 
-    pub fn load(configfile: &Path) -> Self {
-        let fd = std::fs::File::open(configfile)
-            .expect(format!("file {:?} not found", configfile).as_str());
+    pub fn load(configfile: &Path, report: &Report) -> Result<Self> {
+        let fd = std::fs::File::open(configfile).map_err(Error::io(configfile))?;
         log::info!("read proof load info {:?}", configfile);
-        serde_json::from_reader(fd).unwrap()
+        report.input(configfile);
+        serde_json::from_reader(fd).map_err(Error::parse(configfile))
+    }
+
+    /// Records the digests of the params, and of the circuit data and standalone vkey of
+    /// the proofs, which have none recorded yet.
+    pub fn record_digests(&mut self, param_folder: &Path) -> Result<()> {
+        if self.params_digest.is_none() {
+            self.params_digest = Some(file_digest(&param_folder.join(&self.param))?);
+        }
+        for proof in self.proofs.iter_mut() {
+            if proof.circuit_digest.is_none() {
+                proof.circuit_digest = Some(file_digest(&param_folder.join(&proof.circuit))?);
+            }
+            if proof.vkey_file_digest.is_none() {
+                if let Some(vkey_file) = proof.vkey_file(param_folder) {
+                    proof.vkey_file_digest = Some(file_digest(&vkey_file)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the challenge hash and k given on the command line against the recorded
+    /// ones, defaulting to the recorded values.
+    pub fn check_recorded(
+        &self,
+        hashtype: Option<HashType>,
+        k: Option<usize>,
+    ) -> Result<(HashType, usize)> {
+        if let Some(found) = hashtype.filter(|x| *x != self.hashtype) {
+            return Err(Error::HashTypeMismatch {
+                name: self.name.clone(),
+                expected: self.hashtype,
+                found,
+            });
+        }
+        if let Some(found) = k.filter(|x| *x != self.k) {
+            return Err(Error::KMismatch {
+                name: self.name.clone(),
+                expected: self.k,
+                found,
+            });
+        }
+        Ok((self.hashtype, self.k))
     }
 
-    pub fn get_single_info(&self, name: &str, i: usize) -> Self {
-        let mut info = Self::new(name, self.k, self.hashtype);
-        info.append_single_proof(self.proofs[i].clone());
-        info
+    /// Checks the recorded digests against the files in the param folder.
+    pub fn check_digests(&self, param_folder: &Path) -> Result<()> {
+        let check = |path: PathBuf, recorded: &Option<String>| match recorded {
+            Some(expected) => {
+                let found = file_digest(&path)?;
+                if &found != expected {
+                    return Err(Error::DigestMismatch {
+                        path,
+                        expected: expected.clone(),
+                        found,
+                    });
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        check(param_folder.join(&self.param), &self.params_digest)?;
+        for proof in self.proofs.iter() {
+            let circuit_file = param_folder.join(&proof.circuit);
+            match proof.vkey_file(param_folder) {
+                // verifiers holding the standalone vkey may not ship the circuit data
+                Some(vkey_file) if !circuit_file.exists() => {
+                    if proof.vkey_file_digest.is_none() {
+                        // load infos older than the vkey digest, check the manifest instead
+                        verify_file(&vkey_file)?;
+                    }
+                    check(vkey_file, &proof.vkey_file_digest)?;
+                }
+                Some(vkey_file) => {
+                    check(vkey_file, &proof.vkey_file_digest)?;
+                    check(circuit_file, &proof.circuit_digest)?;
+                }
+                None => check(circuit_file, &proof.circuit_digest)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_single_info(&self, name: &str, i: usize) -> Result<Self> {
+        let proof = self
+            .proofs
+            .get(i)
+            .ok_or_else(|| Error::ProofIndexOutOfRange {
+                name: self.name.clone(),
+                proof_idx: i,
+                size: self.proofs.len(),
+            })?;
+        let mut info = Self::new(name, self.k, self.hashtype, OpenSchema::Shplonk);
+        info.open_schema = self.open_schema;
+        info.params_digest = self.params_digest.clone();
+        info.append_single_proof(proof.clone());
+        Ok(info)
     }
 }
 
@@ -182,6 +409,7 @@ pub struct ProofInfo<E: MultiMillerLoop> {
     pub transcripts: Vec<u8>,
     pub k: usize,
     pub hashtype: HashType,
+    pub open_schema: Option<OpenSchema>,
 }
 
 impl<E: MultiMillerLoop> ProofInfo<E> {
@@ -189,36 +417,46 @@ impl<E: MultiMillerLoop> ProofInfo<E> {
         cache_folder: &Path,
         param_folder: &Path,
         loadinfo: &ProofGenerationInfo,
-    ) -> Vec<Self> {
+        report: &Report,
+    ) -> Result<Vec<Self>> {
         let mut proofs = vec![];
         for proof_info in loadinfo.proofs.iter() {
-            let vkey = read_vkey_full::<E>(&param_folder.join(proof_info.circuit.clone()));
+            let vkey = proof_info.read_vkey::<E>(param_folder, report)?;
             println!("loading instance from: {}", proof_info.instance);
-            println!("loading instance size: {}", proof_info.instance_size);
-            let instances = load_instance::<E>(
-                &[proof_info.instance_size],
-                &cache_folder.join(&proof_info.instance),
-            );
-            let transcripts = load_proof(&cache_folder.join(&proof_info.transcript));
+            println!("loading instance size: {:?}", proof_info.instance_size);
+            let instance_file = cache_folder.join(&proof_info.instance);
+            proof_info.check_instance_file::<E::Scalar>(&instance_file)?;
+            let instances = load_instance::<E>(&proof_info.instance_size, &instance_file);
+            let transcript_file = cache_folder.join(&proof_info.transcript);
+            ensure_exists(&transcript_file)?;
+            let transcripts = load_proof(&transcript_file);
+            report.input(&instance_file);
+            report.input(&transcript_file);
             proofs.push(ProofInfo {
                 vkey,
                 instances,
                 k: loadinfo.k,
                 transcripts,
                 hashtype: loadinfo.hashtype,
+                open_schema: loadinfo.open_schema,
             });
         }
-        proofs
+        Ok(proofs)
     }
 
+    /// Verifies the proof with its recorded open schema, `open_scheme` is used when the
+    /// schema is unknown.
     pub fn verify_proof(
         &self,
         params_verifier: &ParamsVerifier<E>,
         open_scheme: OpenSchema,
-    ) -> anyhow::Result<()> {
+        report: &Report,
+    ) -> Result<()> {
+        let open_scheme = self.open_schema.unwrap_or(open_scheme);
+        let _phase = report.phase("verify", format!("k {}", self.k));
         let strategy = SingleVerifier::new(&params_verifier);
 
-        match self.hashtype {
+        let verified = match self.hashtype {
             HashType::Poseidon => verify_proof_ext(
                 params_verifier,
                 &self.vkey,
@@ -230,7 +468,7 @@ impl<E: MultiMillerLoop> ProofInfo<E> {
                     .collect::<Vec<_>>()[..]],
                 &mut PoseidonRead::init(&self.transcripts[..]),
                 open_scheme == OpenSchema::GWC,
-            )?,
+            ),
             HashType::Sha => verify_proof_ext(
                 params_verifier,
                 &self.vkey,
@@ -242,7 +480,7 @@ impl<E: MultiMillerLoop> ProofInfo<E> {
                     .collect::<Vec<_>>()[..]],
                 &mut ShaRead::<_, _, _, sha2::Sha256>::init(&self.transcripts[..]),
                 open_scheme == OpenSchema::GWC,
-            )?,
+            ),
             HashType::Keccak => verify_proof_ext(
                 params_verifier,
                 &self.vkey,
@@ -254,44 +492,69 @@ impl<E: MultiMillerLoop> ProofInfo<E> {
                     .collect::<Vec<_>>()[..]],
                 &mut ShaRead::<_, _, _, sha3::Keccak256>::init(&self.transcripts[..]),
                 open_scheme == OpenSchema::GWC,
-            )?,
+            ),
         };
 
-        Ok(())
+        verified.map_err(|e| Error::VerificationFailed(format!("{:?}", e)))
     }
 }
 
-pub fn load_or_build_unsafe_params<'a, E: MultiMillerLoop>(
+pub fn load_or_build_unsafe_params<E: MultiMillerLoop>(
     k: usize,
     cache_file: &Path,
-    params_cache: &'a mut ParamsCache<E>,
-) -> &'a Params<E::G1Affine> {
+    params_cache: &ParamsCache<E>,
+    report: &Report,
+) -> Result<Arc<Params<E::G1Affine>>> {
     use ark_std::{end_timer, start_timer};
-    let key = cache_file.to_str().unwrap().to_string();
-    if params_cache.contains(&key) {
+    let key = cache_file.to_string_lossy().to_string();
+    if let Some(params) = params_cache.cache.get(&key) {
         log::info!("K param find in cache. Key: {:?}", key);
-        params_cache.cache.get(&key).as_ref().unwrap()
-    } else {
+        return Ok(params);
+    }
+    params_cache.cache.get_or_load(&key, || {
         log::info!("K param not found in cache. Key: {:?}", key);
         let params = if Path::exists(&cache_file) {
+            let _phase = report.phase("params load", cache_file.display());
             let timer = start_timer!(|| "read K param ...");
             log::info!("read params K={} from {:?}", k, cache_file);
-            let mut fd = std::fs::File::open(&cache_file).unwrap();
-            let params = Params::<E::G1Affine>::read(&mut fd).unwrap();
+            let origin = verify_file(cache_file)?;
+            if params_cache.require_trusted_params {
+                let reason = match origin {
+                    Some(origin) => origin.untrusted_reason(),
+                    None => Some("has no manifest".to_string()),
+                };
+                if let Some(reason) = reason {
+                    return Err(Error::UntrustedParams {
+                        path: cache_file.to_path_buf(),
+                        reason,
+                    });
+                }
+            }
+            let mut fd = std::fs::File::open(&cache_file).map_err(Error::io(cache_file))?;
+            let params = Params::<E::G1Affine>::read(&mut fd).map_err(Error::io(cache_file))?;
+            report.input(cache_file);
             end_timer!(timer);
             params
+        } else if params_cache.require_trusted_params {
+            return Err(Error::UntrustedParams {
+                path: cache_file.to_path_buf(),
+                reason: "not found".to_string(),
+            });
         } else {
+            let _phase = report.phase("params setup", cache_file.display());
             let params = Params::<E::G1Affine>::unsafe_setup::<E>(k as u32);
 
             log::info!("write params K={} to {:?}", k, cache_file);
             let timer = start_timer!(|| "begin write params file ...");
-            let mut fd = std::fs::File::create(&cache_file).unwrap();
-            params.write(&mut fd).unwrap();
+            let mut fd = std::fs::File::create(&cache_file).map_err(Error::io(cache_file))?;
+            params.write(&mut fd).map_err(Error::io(cache_file))?;
+            FileManifest::record(cache_file, Origin::UnsafeSetup)?;
+            report.output(cache_file);
             end_timer!(timer);
             params
         };
-        params_cache.push(key, params)
-    }
+        Ok((params, file_size(cache_file)?))
+    })
 }
 
 pub trait Prover {
@@ -303,7 +566,7 @@ pub trait Prover {
         pkey: &ProvingKey<E::G1Affine>,
         hashtype: HashType,
         schema: OpenSchema,
-    ) -> Vec<u8>;
+    ) -> Result<Vec<u8>>;
 
     fn create_witness<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
         &self,
@@ -313,15 +576,16 @@ pub trait Prover {
         k: usize,
         cache_folder: &Path,
         param_folder: &Path,
-        pkey_cache: &mut ProvingKeyCache<E>,
-        params_cache: &mut ParamsCache<E>,
-    );
+        pkey_cache: &ProvingKeyCache<E>,
+        params_cache: &ParamsCache<E>,
+        report: &Report,
+    ) -> Result<()>;
     fn mock_proof<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
         &self,
         k: u32,
         c: &C,
         instances: &Vec<Vec<E::Scalar>>,
-    );
+    ) -> Result<()>;
 }
 
 impl ProofPieceInfo {
@@ -330,13 +594,17 @@ impl ProofPieceInfo {
         instances: &Vec<Vec<F>>,
         transcript: &Vec<u8>,
         cache_folder: &Path,
-    ) {
+        report: &Report,
+    ) -> Result<()> {
         // store instance in instance file
         store_instance(instances, &cache_folder.join(self.instance.as_str()));
         let cache_file = &cache_folder.join(&self.transcript);
         log::debug!("create transcripts file {:?}", cache_file);
-        let mut fd = std::fs::File::create(&cache_file).unwrap();
-        fd.write_all(transcript).unwrap();
+        let mut fd = std::fs::File::create(&cache_file).map_err(Error::io(cache_file))?;
+        fd.write_all(transcript).map_err(Error::io(cache_file))?;
+        report.output(&cache_folder.join(&self.instance));
+        report.output(cache_file);
+        Ok(())
     }
 
     pub fn exec_create_proof<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
@@ -344,14 +612,109 @@ impl ProofPieceInfo {
         c: &C,
         instances: &Vec<Vec<E::Scalar>>,
         k: usize,
-        pkey_cache: &mut ProvingKeyCache<E>,
-        param_cache: &mut ParamsCache<E>,
+        pkey_cache: &ProvingKeyCache<E>,
+        param_cache: &ParamsCache<E>,
         hashtype: HashType,
         schema: OpenSchema,
-    ) -> Vec<u8> {
-        let params = param_cache.generate_k_params(k);
-        let pkey = pkey_cache.load_or_build_pkey::<C>(c, &params, self.circuit.clone());
-        self.create_proof::<E, C>(c, instances, params, pkey, hashtype, schema)
+        report: &Report,
+    ) -> Result<Vec<u8>> {
+        let params = param_cache.generate_k_params(k, report)?;
+        let pkey = pkey_cache.load_or_build_pkey::<C>(c, &params, self.circuit.clone(), report)?;
+        let _phase = report.phase("prove", &self.circuit);
+        self.create_proof::<E, C>(c, instances, &params, &pkey, hashtype, schema)
+    }
+
+    /// Proves the piece from its witness file and stored instances, which are both
+    /// produced by `Prover::create_witness`. The circuit is only needed through its
+    /// circuit data in the params folder.
+    pub fn exec_create_proof_from_witness<E: MultiMillerLoop>(
+        &self,
+        k: usize,
+        cache_folder: &Path,
+        pkey_cache: &ProvingKeyCache<E>,
+        param_cache: &ParamsCache<E>,
+        hashtype: HashType,
+        schema: OpenSchema,
+        report: &Report,
+    ) -> Result<(Vec<Vec<E::Scalar>>, Vec<u8>)> {
+        use ark_std::rand::rngs::OsRng;
+        use ark_std::{end_timer, start_timer};
+        use halo2_proofs::plonk::create_proof_from_witness;
+        use halo2_proofs::plonk::create_proof_from_witness_with_shplonk;
+
+        let instance_file = cache_folder.join(&self.instance);
+        self.check_instance_file::<E::Scalar>(&instance_file)?;
+        let instances = load_instance::<E>(&self.instance_size, &instance_file);
+        let witness_file = cache_folder.join(&self.witness);
+        report.input(&instance_file);
+        report.input(&witness_file);
+
+        let params = param_cache.generate_k_params(k, report)?;
+        let pkey = pkey_cache.load_pkey(&params, self.circuit.clone(), report)?;
+
+        let instance_slices = instances.iter().map(|x| &x[..]).collect::<Vec<_>>();
+
+        macro_rules! witness_gen_proof {
+            ($transcript: expr) => {{
+                log::info!("read witness file {:?}", witness_file);
+                let mut fd =
+                    std::fs::File::open(&witness_file).map_err(Error::io(&witness_file))?;
+                match schema {
+                    OpenSchema::GWC => create_proof_from_witness(
+                        &params,
+                        &pkey,
+                        [instance_slices.as_slice()].as_slice(),
+                        OsRng,
+                        &mut $transcript,
+                        &mut fd,
+                    ),
+                    OpenSchema::Shplonk => create_proof_from_witness_with_shplonk(
+                        &params,
+                        &pkey,
+                        [instance_slices.as_slice()].as_slice(),
+                        OsRng,
+                        &mut $transcript,
+                        &mut fd,
+                    ),
+                }
+                .map_err(Error::halo2)?;
+                $transcript.finalize()
+            }};
+        }
+
+        let _phase = report.phase("prove", &self.circuit);
+        let timer = start_timer!(|| "creating proof from witness ...");
+        let transcripts = match hashtype {
+            HashType::Poseidon => {
+                let mut transcript = PoseidonWrite::init(vec![]);
+                witness_gen_proof!(transcript)
+            }
+            HashType::Sha => {
+                let mut transcript = ShaWrite::<_, _, _, sha2::Sha256>::init(vec![]);
+                witness_gen_proof!(transcript)
+            }
+            HashType::Keccak => {
+                let mut transcript = ShaWrite::<_, _, _, sha3::Keccak256>::init(vec![]);
+                witness_gen_proof!(transcript)
+            }
+        };
+        end_timer!(timer);
+
+        let inputs_size = instances.iter().fold(0, |acc, x| usize::max(acc, x.len()));
+        let params_verifier: ParamsVerifier<E> =
+            params.verifier(inputs_size).map_err(Error::halo2)?;
+        ProofInfo::<E> {
+            vkey: pkey.get_vk().clone(),
+            instances: instances.clone(),
+            transcripts: transcripts.clone(),
+            k,
+            hashtype,
+            open_schema: Some(schema),
+        }
+        .verify_proof(&params_verifier, schema, report)?;
+        log::info!("verify halo2 proof succeed");
+
+        Ok((instances, transcripts))
     }
 }
 
@@ -364,14 +727,15 @@ impl Prover for ProofPieceInfo {
         pkey: &ProvingKey<E::G1Affine>,
         hashtype: HashType,
         schema: OpenSchema,
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>> {
         use ark_std::{end_timer, start_timer};
 
         let inputs_size = instances.iter().fold(0, |acc, x| usize::max(acc, x.len()));
 
         let instances: Vec<&[E::Scalar]> = instances.iter().map(|x| &x[..]).collect::<Vec<_>>();
 
-        let params_verifier: ParamsVerifier<E> = params.verifier(inputs_size).unwrap();
+        let params_verifier: ParamsVerifier<E> =
+            params.verifier(inputs_size).map_err(Error::halo2)?;
         let strategy = SingleVerifier::new(&params_verifier);
 
         #[cfg(feature = "perf")]
@@ -410,7 +774,7 @@ impl Prover for ProofPieceInfo {
                         advices,
                         &mut $transcript,
                     )
-                    .expect("proof generation should not fail"),
+                    .map_err(Error::halo2)?,
                     OpenSchema::Shplonk => create_proof_from_advices_with_shplonk(
                         &params,
                         pkey,
@@ -418,7 +782,7 @@ impl Prover for ProofPieceInfo {
                         advices,
                         &mut $transcript,
                     )
-                    .expect("proof generation should not fail"),
+                    .map_err(Error::halo2)?,
                 }
             }};
         }
@@ -438,7 +802,7 @@ impl Prover for ProofPieceInfo {
                         OsRng,
                         &mut $transcript,
                     )
-                    .expect("proof generation should not fail"),
+                    .map_err(Error::halo2)?,
                     OpenSchema::Shplonk => create_proof_with_shplonk(
                         &params,
                         &pkey,
@@ -447,7 +811,7 @@ impl Prover for ProofPieceInfo {
                         OsRng,
                         &mut $transcript,
                     )
-                    .expect("proof generation should not fail"),
+                    .map_err(Error::halo2)?,
                 }
             };
         }
@@ -462,7 +826,7 @@ impl Prover for ProofPieceInfo {
                         &[&instances.iter().map(|x| &x[..]).collect::<Vec<_>>()[..]],
                         &mut $reader,
                     )
-                    .unwrap(),
+                    .map_err(|e| Error::VerificationFailed(format!("{:?}", e)))?,
                     OpenSchema::Shplonk => verify_proof_with_shplonk(
                         &params_verifier,
                         &pkey.get_vk(),
@@ -470,7 +834,7 @@ impl Prover for ProofPieceInfo {
                         &[&instances.iter().map(|x| &x[..]).collect::<Vec<_>>()[..]],
                         &mut $reader,
                     )
-                    .unwrap(),
+                    .map_err(|e| Error::VerificationFailed(format!("{:?}", e)))?,
                 }
                 log::info!("verify halo2 proof succeed");
             };
@@ -513,7 +877,7 @@ impl Prover for ProofPieceInfo {
             }
         };
         end_timer!(timer);
-        r
+        Ok(r)
     }
 
     fn create_witness<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
@@ -524,12 +888,17 @@ impl Prover for ProofPieceInfo {
         k: usize,
         cache_folder: &Path,
         param_folder: &Path,
-        pkey_cache: &mut ProvingKeyCache<E>,
-        param_cache: &mut ParamsCache<E>,
-    ) {
-        let params =
-            load_or_build_unsafe_params::<E>(k, &param_folder.join(&param_file), param_cache);
-        let pkey = pkey_cache.load_or_build_pkey::<C>(&c, &params, self.circuit.clone());
+        pkey_cache: &ProvingKeyCache<E>,
+        param_cache: &ParamsCache<E>,
+        report: &Report,
+    ) -> Result<()> {
+        let params = load_or_build_unsafe_params::<E>(
+            k,
+            &param_folder.join(&param_file),
+            param_cache,
+            report,
+        )?;
+        let pkey = pkey_cache.load_or_build_pkey::<C>(&c, &params, self.circuit.clone(), report)?;
 
         let witness_file = &cache_folder.join(self.witness.clone());
 
@@ -541,11 +910,11 @@ impl Prover for ProofPieceInfo {
             .create(true)
             .truncate(true)
             .open(witness_file)
-            .unwrap();
+            .map_err(Error::io(witness_file))?;
 
         create_witness(
             &params,
-            pkey,
+            &pkey,
             c,
             instances
                 .iter()
@@ -554,7 +923,11 @@ impl Prover for ProofPieceInfo {
                 .as_slice(),
             &mut fd,
         )
-        .unwrap()
+        .map_err(Error::halo2)?;
+
+        // the instances are needed to prove from the witness elsewhere
+        store_instance(instances, &cache_folder.join(self.instance.as_str()));
+        Ok(())
     }
 
     fn mock_proof<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
@@ -562,53 +935,60 @@ impl Prover for ProofPieceInfo {
         k: u32,
         c: &C,
         instances: &Vec<Vec<E::Scalar>>,
-    ) {
-        let prover = MockProver::run(k, c, instances.clone()).unwrap();
-        assert_eq!(prover.verify(), Ok(()));
+    ) -> Result<()> {
+        let prover = MockProver::run(k, c, instances.clone()).map_err(Error::halo2)?;
+        prover
+            .verify()
+            .map_err(|e| Error::VerificationFailed(format!("{:?}", e)))
     }
 }
 
 pub fn load_vkey<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
     params: &Params<E::G1Affine>,
     param_folder: &Path,
-) -> VerifyingKey<E::G1Affine> {
+) -> Result<VerifyingKey<E::G1Affine>> {
     log::info!("read vkey from {:?}", param_folder);
-    let mut fd = std::fs::File::open(&param_folder).unwrap();
-    VerifyingKey::read::<_, C>(&mut fd, params).unwrap()
+    let mut fd = std::fs::File::open(&param_folder).map_err(Error::io(param_folder))?;
+    VerifyingKey::read::<_, C>(&mut fd, params).map_err(Error::io(param_folder))
 }
 
-pub fn load_or_build_pkey<'a, E: MultiMillerLoop, C: Circuit<E::Scalar>>(
+pub fn load_or_build_pkey<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
     params: &Params<E::G1Affine>,
     circuit: &C,
     cache_file: &Path,
     vkey_file: &Path,
-    pkey_cache: &'a mut ProvingKeyCache<E>,
-) -> &'a ProvingKey<E::G1Affine> {
+    pkey_cache: &ProvingKeyCache<E>,
+    report: &Report,
+) -> Result<Arc<ProvingKey<E::G1Affine>>> {
     use ark_std::{end_timer, start_timer};
-    let key = cache_file.to_str().unwrap().to_string();
-    if pkey_cache.contains(&key) {
+    let key = cache_file.to_string_lossy().to_string();
+    if let Some(pkey) = pkey_cache.cache.get(&key) {
         log::info!("pkey find in cache. Key: {:?}", &key);
-        pkey_cache.cache.get(&key).as_ref().unwrap()
-    } else {
+        return Ok(pkey);
+    }
+    pkey_cache.cache.get_or_load(&key, || {
         log::info!("pkey not found in cache. Key: {:?}", &key);
         let pkey = if Path::exists(&cache_file) {
+            let _phase = report.phase("pkey load", cache_file.display());
             let timer = start_timer!(|| "test read info full ...");
-            let pkey = read_pk_full::<E>(&params, &cache_file);
+            let pkey = read_pk::<E>(&params, &cache_file, pkey_cache.mmap, report)?;
             //assert_eq!(vkey.domain, pkey.get_vk().domain);
             //assert_eq!(vkey.fixed_commitments, pkey.get_vk().fixed_commitments);
             end_timer!(timer);
             pkey
         } else {
-            let vkey = load_or_build_vkey::<E, C>(params, circuit, Some(vkey_file));
-            let pkey =
-                keygen_pk(&params, vkey.clone(), circuit).expect("keygen_pk should not fail");
+            let _phase = report.phase("keygen", cache_file.display());
+            let vkey = load_or_build_vkey::<E, C>(params, circuit, None);
+            store_vkey::<E>(&vkey, vkey_file, report)?;
+            let pkey = keygen_pk(&params, vkey.clone(), circuit).map_err(Error::halo2)?;
             let timer = start_timer!(|| "test storing info full ...");
-            store_info_full::<E, C>(&params, vkey, circuit, cache_file);
+            store_info_full::<E, C>(&params, vkey, circuit, cache_file, report)?;
             end_timer!(timer);
             pkey
         };
-        pkey_cache.push(key, pkey)
-    }
+        // the circuit data file is the estimate of the size of the proving key
+        Ok((pkey, file_size(cache_file)?))
+    })
 }
 
 fn store_info_full<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
@@ -616,7 +996,8 @@ fn store_info_full<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
     vkey: VerifyingKey<E::G1Affine>,
     circuit: &C,
     cache_file: &Path,
-) {
+    report: &Report,
+) -> Result<()> {
     log::info!("store vkey full to {:?}", cache_file);
     let mut fd = OpenOptions::new()
         .read(true)
@@ -624,31 +1005,92 @@ fn store_info_full<E: MultiMillerLoop, C: Circuit<E::Scalar>>(
         .create(true)
         .truncate(true)
         .open(&cache_file)
-        .unwrap();
-    let data = CircuitData::new(params, vkey, circuit).unwrap();
-    data.write(&mut fd).unwrap();
+        .map_err(Error::io(cache_file))?;
+    let data = CircuitData::new(params, vkey, circuit).map_err(Error::halo2)?;
+    data.write(&mut fd).map_err(Error::io(cache_file))?;
+    FileManifest::record(cache_file, Origin::Keygen)?;
+    report.output(cache_file);
+    Ok(())
+}
+
+/// Writes the vkey in the format of the head of circuit data, so that `read_vkey_full`
+/// reads both.
+pub(crate) fn store_vkey<E: MultiMillerLoop>(
+    vkey: &VerifyingKey<E::G1Affine>,
+    vkey_file: &Path,
+    report: &Report,
+) -> Result<()> {
+    log::info!("store vkey to {:?}", vkey_file);
+    let mut fd = std::fs::File::create(vkey_file).map_err(Error::io(vkey_file))?;
+    vkey.store(&mut fd).map_err(Error::io(vkey_file))?;
+    FileManifest::record(vkey_file, Origin::Keygen)?;
+    report.output(vkey_file);
+    Ok(())
 }
 
-pub(crate) fn read_vkey_full<E: MultiMillerLoop>(cache_file: &Path) -> VerifyingKey<E::G1Affine> {
+pub(crate) fn read_vkey_full<E: MultiMillerLoop>(
+    cache_file: &Path,
+    report: &Report,
+) -> Result<VerifyingKey<E::G1Affine>> {
     log::info!("read vkey full from {:?}", cache_file);
-    let mut fd = std::fs::File::open(&cache_file).unwrap();
-    VerifyingKey::<E::G1Affine>::fetch(&mut fd).unwrap()
+    let mut fd = std::fs::File::open(&cache_file).map_err(Error::io(cache_file))?;
+    report.input(cache_file);
+    VerifyingKey::<E::G1Affine>::fetch(&mut fd).map_err(Error::io(cache_file))
 }
 
 pub(crate) fn read_pk_full<E: MultiMillerLoop>(
     params: &Params<E::G1Affine>,
     cache_file: &Path,
-) -> ProvingKey<E::G1Affine> {
+    report: &Report,
+) -> Result<ProvingKey<E::G1Affine>> {
     use ark_std::{end_timer, start_timer};
     let timer = start_timer!(|| "fetch vkey full ...");
     log::info!("read vkey full from {:?}", cache_file);
-    let mut fd = std::fs::File::open(&cache_file).unwrap();
+    verify_file(cache_file)?;
+    report.input(cache_file);
+    let mut fd = std::fs::File::open(&cache_file).map_err(Error::io(cache_file))?;
     end_timer!(timer);
     let timer = start_timer!(|| "fetch pk full ...");
-    let circuit_data = CircuitData::read(&mut fd).unwrap();
+    let circuit_data = CircuitData::read(&mut fd).map_err(Error::io(cache_file))?;
+    let pk = circuit_data.into_proving_key(params);
+    end_timer!(timer);
+    Ok(pk)
+}
+
+/// Reads the proving key from the memory mapped circuit data file. The manifest digest is
+/// computed over the mapped pages, which are then deserialized from memory instead of
+/// through many small reads of the file. The polynomials of the key are still
+/// materialized by `CircuitData::into_proving_key`, as `ProvingKey` owns them.
+pub(crate) fn read_pk_mmap<E: MultiMillerLoop>(
+    params: &Params<E::G1Affine>,
+    cache_file: &Path,
+    report: &Report,
+) -> Result<ProvingKey<E::G1Affine>> {
+    use ark_std::{end_timer, start_timer};
+    log::info!("map vkey full from {:?}", cache_file);
+    let fd = std::fs::File::open(&cache_file).map_err(Error::io(cache_file))?;
+    // the circuit data files are only written by keygen, before they are loaded
+    let mmap = unsafe { memmap2::Mmap::map(&fd) }.map_err(Error::io(cache_file))?;
+    verify_mapped_file(cache_file, &mmap[..])?;
+    report.input(cache_file);
+    let timer = start_timer!(|| "fetch pk full from mmap ...");
+    let circuit_data = CircuitData::read(&mut &mmap[..]).map_err(Error::io(cache_file))?;
     let pk = circuit_data.into_proving_key(params);
     end_timer!(timer);
-    pk
+    Ok(pk)
+}
+
+fn read_pk<E: MultiMillerLoop>(
+    params: &Params<E::G1Affine>,
+    cache_file: &Path,
+    mmap: bool,
+    report: &Report,
+) -> Result<ProvingKey<E::G1Affine>> {
+    if mmap {
+        read_pk_mmap::<E>(params, cache_file, report)
+    } else {
+        read_pk_full::<E>(params, cache_file, report)
+    }
 }
 
 #[test]
@@ -660,20 +1102,19 @@ fn batch_single_circuit() {
     use halo2_proofs::pairing::bn256::Bn256;
     use halo2_proofs::pairing::bn256::Fr;
     use std::path::Path;
-    use std::sync::Mutex;
 
     const DEFAULT_CACHE_SIZE: usize = 5;
 
     env_logger::init();
 
     lazy_static::lazy_static! {
-    pub static ref K_PARAMS_CACHE: Mutex<ParamsCache<Bn256>> =
-        Mutex::new(ParamsCache::new(DEFAULT_CACHE_SIZE, PathBuf::from("./params")));
+    pub static ref K_PARAMS_CACHE: ParamsCache<Bn256> =
+        ParamsCache::new(DEFAULT_CACHE_SIZE, PathBuf::from("./params"));
     }
 
     lazy_static::lazy_static! {
-    pub static ref PKEY_CACHE: Mutex<ProvingKeyCache<Bn256>> =
-        Mutex::new(ProvingKeyCache::new(DEFAULT_CACHE_SIZE, PathBuf::from("./params")));
+    pub static ref PKEY_CACHE: ProvingKeyCache<Bn256> =
+        ProvingKeyCache::new(DEFAULT_CACHE_SIZE, PathBuf::from("./params"));
     }
 
     const K: u32 = 22;
@@ -681,8 +1122,12 @@ fn batch_single_circuit() {
     let cache_folder = Path::new("output");
     let params_folder = Path::new("params");
 
-    let mut proof_load_info =
-        ProofGenerationInfo::new("test_circuit", K as usize, HashType::Poseidon);
+    let mut proof_load_info = ProofGenerationInfo::new(
+        "test_circuit",
+        K as usize,
+        HashType::Poseidon,
+        OpenSchema::Shplonk,
+    );
 
     {
         let circuit = SimpleCircuit::<Fr> {
@@ -695,32 +1140,42 @@ fn batch_single_circuit() {
         let circuit_info = ProofPieceInfo::new("test_circuit".to_string(), 0, 1, None);
 
         // testing proof
-        circuit_info.mock_proof::<Bn256, _>(K, &circuit, &instances);
+        circuit_info
+            .mock_proof::<Bn256, _>(K, &circuit, &instances)
+            .unwrap();
 
         if false {
-            circuit_info.create_witness(
+            circuit_info
+                .create_witness(
+                    &circuit,
+                    &instances,
+                    param_file.clone(),
+                    K as usize,
+                    &cache_folder,
+                    params_folder,
+                    &*PKEY_CACHE,
+                    &*K_PARAMS_CACHE,
+                    &Report::default(),
+                )
+                .unwrap();
+        }
+
+        let transcripts = circuit_info
+            .exec_create_proof(
                 &circuit,
                 &instances,
-                param_file.clone(),
                 K as usize,
-                &cache_folder,
-                params_folder,
-                PKEY_CACHE.lock().as_mut().unwrap(),
-                K_PARAMS_CACHE.lock().as_mut().unwrap(),
-            );
-        }
-
-        let transcripts = circuit_info.exec_create_proof(
-            &circuit,
-            &instances,
-            K as usize,
-            PKEY_CACHE.lock().as_mut().unwrap(),
-            K_PARAMS_CACHE.lock().as_mut().unwrap(),
-            HashType::Poseidon,
-            OpenSchema::Shplonk,
-        );
+                &*PKEY_CACHE,
+                &*K_PARAMS_CACHE,
+                HashType::Poseidon,
+                OpenSchema::Shplonk,
+                &Report::default(),
+            )
+            .unwrap();
 
-        circuit_info.save_proof_data(&instances, &transcripts, cache_folder);
+        circuit_info
+            .save_proof_data(&instances, &transcripts, cache_folder, &Report::default())
+            .unwrap();
 
         proof_load_info.append_single_proof(circuit_info);
     }
@@ -734,20 +1189,123 @@ fn batch_single_circuit() {
         let instances = vec![vec![Fr::from(300u64)]];
         let circuit_info = ProofPieceInfo::new("test_circuit".to_string(), 1, 1, None);
 
-        let transcripts = circuit_info.exec_create_proof(
-            &circuit,
-            &instances,
-            K as usize,
-            PKEY_CACHE.lock().as_mut().unwrap(),
-            K_PARAMS_CACHE.lock().as_mut().unwrap(),
-            HashType::Poseidon,
-            OpenSchema::Shplonk,
-        );
+        let transcripts = circuit_info
+            .exec_create_proof(
+                &circuit,
+                &instances,
+                K as usize,
+                &*PKEY_CACHE,
+                &*K_PARAMS_CACHE,
+                HashType::Poseidon,
+                OpenSchema::Shplonk,
+                &Report::default(),
+            )
+            .unwrap();
 
-        circuit_info.save_proof_data(&instances, &transcripts, cache_folder);
+        circuit_info
+            .save_proof_data(&instances, &transcripts, cache_folder, &Report::default())
+            .unwrap();
 
         proof_load_info.append_single_proof(circuit_info);
     }
 
-    proof_load_info.save(cache_folder);
+    proof_load_info
+        .save(cache_folder, &Report::default())
+        .unwrap();
+}
+
+#[test]
+fn instance_size_of_single_and_multiple_columns() {
+    let piece = |instance_size: &str| {
+        format!(
+            r#"{{"circuit": "test.circuit.data", "instance_size": {}, "witness": "test.0.witness.data", "instance": "test.0.instance.data", "transcript": "test.0.transcript.data"}}"#,
+            instance_size
+        )
+    };
+
+    // older load infos record the size of their single column
+    let single: ProofPieceInfo = serde_json::from_str(&piece("3")).unwrap();
+    assert_eq!(single.instance_size, vec![3]);
+    let columns: ProofPieceInfo = serde_json::from_str(&piece("[1, 2]")).unwrap();
+    assert_eq!(columns.instance_size, vec![1, 2]);
+    assert!(serde_json::from_str::<ProofPieceInfo>(&piece("\"3\"")).is_err());
+
+    // both are written back as a list of column sizes
+    for info in [single, columns] {
+        let json = serde_json::to_string(&info).unwrap();
+        let read: ProofPieceInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.instance_size, info.instance_size);
+        assert!(json.contains(&format!("{:?}", info.instance_size).replace(' ', "")));
+    }
+}
+
+#[test]
+fn prove_from_witness() {
+    use crate::proof::Prover;
+    use crate::samples::simple::SimpleCircuit;
+    use halo2_proofs::pairing::bn256::Bn256;
+    use halo2_proofs::pairing::bn256::Fr;
+
+    const K: usize = 12;
+    let cache_folder = Path::new("output");
+    let params_folder = PathBuf::from("params");
+    std::fs::create_dir_all(cache_folder).unwrap();
+    std::fs::create_dir_all(&params_folder).unwrap();
+    let params_cache = ParamsCache::<Bn256>::new(1, params_folder.clone());
+    let pkey_cache = ProvingKeyCache::<Bn256>::new(1, params_folder.clone());
+
+    let circuit = SimpleCircuit::<Fr> {
+        a: Fr::from(100u64),
+        b: Fr::from(200u64),
+    };
+    let instances = vec![vec![Fr::from(300u64)]];
+    let piece = ProofPieceInfo::new("test_witness".to_string(), 0, 1, None);
+    piece
+        .create_witness(
+            &circuit,
+            &instances,
+            format!("K{}.params", K),
+            K,
+            cache_folder,
+            &params_folder,
+            &pkey_cache,
+            &params_cache,
+            &Report::default(),
+        )
+        .unwrap();
+
+    // proves from the witness and instance files, verifying the proof
+    let (proved_instances, transcripts) = piece
+        .exec_create_proof_from_witness(
+            K,
+            cache_folder,
+            &pkey_cache,
+            &params_cache,
+            HashType::Poseidon,
+            OpenSchema::Shplonk,
+            &Report::default(),
+        )
+        .unwrap();
+    assert_eq!(proved_instances, instances);
+    assert!(!transcripts.is_empty());
+
+    // a truncated instance file fails instead of panicking in `load_instance`
+    std::fs::write(cache_folder.join(&piece.instance), [0u8; 16]).unwrap();
+    let truncated = piece.exec_create_proof_from_witness(
+        K,
+        cache_folder,
+        &pkey_cache,
+        &params_cache,
+        HashType::Poseidon,
+        OpenSchema::Shplonk,
+        &Report::default(),
+    );
+    assert!(matches!(
+        truncated,
+        Err(Error::SizeMismatch {
+            expected: 32,
+            found: 16,
+            ..
+        })
+    ));
 }
@@ -0,0 +1,285 @@
+use crate::args::HashType;
+use crate::args::OpenSchema;
+use crate::cache::CacheStats;
+use crate::error::Error;
+use crate::error::Result;
+use crate::exec::ProofVerification;
+use crate::utils::field_to_hex;
+use ff::PrimeField;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Duration of a phase of the run, e.g. loading the params of a k.
+#[derive(Clone, Serialize, Debug)]
+pub struct PhaseReport {
+    pub phase: String,
+    /// File or circuit the phase worked on.
+    pub target: String,
+    pub seconds: f64,
+}
+
+/// Commitment checks of an aggregate circuit, resolved to proof and column indices.
+#[derive(Clone, Serialize, Debug)]
+pub struct CommitmentsReport {
+    pub is_final: bool,
+    /// (source proof, source column, target proof, target column)
+    pub equivalents: Vec<[usize; 4]>,
+    /// (proof, column)
+    pub expose: Vec<[usize; 2]>,
+    /// ((proof, instance column, instance), (proof, column))
+    pub absorb: Vec<([usize; 3], [usize; 2])>,
+}
+
+/// A proof created by the run.
+#[derive(Clone, Serialize, Debug)]
+pub struct ProofReport {
+    pub circuit: String,
+    pub transcript: String,
+    pub k: usize,
+    pub hash: HashType,
+    pub open_schema: OpenSchema,
+    /// Instances of each column as hex of their field representation, see `field_to_hex`.
+    pub instances: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct CacheReport {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub load_seconds: f64,
+    pub bytes_resident: u64,
+}
+
+impl From<CacheStats> for CacheReport {
+    fn from(stats: CacheStats) -> Self {
+        CacheReport {
+            hits: stats.hits,
+            misses: stats.misses,
+            evictions: stats.evictions,
+            load_seconds: stats.load_time.as_secs_f64(),
+            bytes_resident: stats.bytes_resident,
+        }
+    }
+}
+
+/// Report of a cli run, written as json with `--report <file>`.
+#[derive(Clone, Serialize, Debug)]
+pub struct RunReport {
+    pub command: String,
+    /// `ok` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub exit_code: i32,
+    pub seconds: f64,
+    /// Settings chosen for the run, e.g. the batch k resolved by `--auto-k`.
+    pub settings: BTreeMap<String, String>,
+    pub inputs: Vec<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    pub commitments: Vec<CommitmentsReport>,
+    pub proofs: Vec<ProofReport>,
+    pub verifications: Vec<ProofVerification>,
+    pub phases: Vec<PhaseReport>,
+    pub caches: BTreeMap<String, CacheReport>,
+    #[serde(skip)]
+    start: Option<Instant>,
+}
+
+/// Handle on the report of a single run, passed to the functions working for that run.
+/// Clones record into the same report, so that concurrent runs sharing the caches keep
+/// their own reports. The default handle records nothing.
+#[derive(Clone, Default)]
+pub struct Report {
+    run: Option<Arc<Mutex<RunReport>>>,
+}
+
+impl Report {
+    /// Starts recording the report of the command.
+    pub fn new(command: &str) -> Self {
+        Report {
+            run: Some(Arc::new(Mutex::new(RunReport {
+                command: command.to_string(),
+                status: "running".to_string(),
+                error: None,
+                exit_code: 0,
+                seconds: 0.0,
+                settings: BTreeMap::new(),
+                inputs: vec![],
+                outputs: vec![],
+                commitments: vec![],
+                proofs: vec![],
+                verifications: vec![],
+                phases: vec![],
+                caches: BTreeMap::new(),
+                start: Some(Instant::now()),
+            }))),
+        }
+    }
+
+    /// Applies `f` to the report of the run, nothing is recorded by a default handle.
+    fn record<F: FnOnce(&mut RunReport)>(&self, f: F) {
+        if let Some(run) = self.run.as_ref() {
+            // a panicked recording leaves the report consistent enough to be written
+            f(&mut run.lock().unwrap_or_else(|e| e.into_inner()))
+        }
+    }
+
+    pub fn input(&self, file: &Path) {
+        self.record(|report| {
+            if !report.inputs.iter().any(|x| x == file) {
+                report.inputs.push(file.to_path_buf())
+            }
+        })
+    }
+
+    pub fn output(&self, file: &Path) {
+        self.record(|report| {
+            if !report.outputs.iter().any(|x| x == file) {
+                report.outputs.push(file.to_path_buf())
+            }
+        })
+    }
+
+    pub fn setting<V: ToString>(&self, key: &str, value: V) {
+        self.record(|report| {
+            report.settings.insert(key.to_string(), value.to_string());
+        })
+    }
+
+    pub fn commitments(
+        &self,
+        is_final: bool,
+        equivalents: &Vec<[usize; 4]>,
+        expose: &Vec<[usize; 2]>,
+        absorb: &Vec<([usize; 3], [usize; 2])>,
+    ) {
+        self.record(|report| {
+            report.commitments.push(CommitmentsReport {
+                is_final,
+                equivalents: equivalents.clone(),
+                expose: expose.clone(),
+                absorb: absorb.clone(),
+            })
+        })
+    }
+
+    pub fn proof<F: PrimeField>(
+        &self,
+        circuit: &str,
+        transcript: &str,
+        k: usize,
+        hash: HashType,
+        open_schema: OpenSchema,
+        instances: &Vec<Vec<F>>,
+    ) {
+        self.record(|report| {
+            report.proofs.push(ProofReport {
+                circuit: circuit.to_string(),
+                transcript: transcript.to_string(),
+                k,
+                hash,
+                open_schema,
+                instances: instances
+                    .iter()
+                    .map(|column| column.iter().map(field_to_hex).collect())
+                    .collect(),
+            })
+        })
+    }
+
+    pub fn verifications(&self, results: &Vec<ProofVerification>) {
+        self.record(|report| report.verifications.extend(results.iter().cloned()))
+    }
+
+    pub fn cache(&self, name: &str, stats: CacheStats) {
+        self.record(|report| {
+            report.caches.insert(name.to_string(), stats.into());
+        })
+    }
+
+    pub fn phase<T: ToString>(&self, phase: &'static str, target: T) -> Phase<'_> {
+        Phase {
+            report: self,
+            phase,
+            target: target.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the outcome of the run and writes the report to the file.
+    pub fn finish(&self, file: &Path, result: &Result<()>) -> Result<()> {
+        let run = match self.run.as_ref() {
+            Some(run) => run,
+            None => return Ok(()),
+        };
+        let mut report = run.lock().unwrap_or_else(|e| e.into_inner());
+        report.seconds = report.start.map_or(0.0, |x| x.elapsed().as_secs_f64());
+        match result {
+            Ok(()) => report.status = "ok".to_string(),
+            Err(e) => {
+                report.status = "failed".to_string();
+                report.error = Some(e.to_string());
+                report.exit_code = e.exit_code();
+            }
+        }
+        log::info!("write run report {:?}", file);
+        let fd = std::fs::File::create(file).map_err(Error::io(file))?;
+        serde_json::to_writer_pretty(fd, &*report).map_err(Error::serialize(file))
+    }
+}
+
+/// Times a phase of the run until it is dropped.
+pub struct Phase<'a> {
+    report: &'a Report,
+    phase: &'static str,
+    target: String,
+    start: Instant,
+}
+
+impl<'a> Drop for Phase<'a> {
+    fn drop(&mut self) {
+        let seconds = self.start.elapsed().as_secs_f64();
+        let phase = self.phase.to_string();
+        let target = std::mem::take(&mut self.target);
+        self.report.record(|report| {
+            report.phases.push(PhaseReport {
+                phase,
+                target,
+                seconds,
+            })
+        })
+    }
+}
+
+#[test]
+fn reports_of_concurrent_runs_stay_apart() {
+    let first = Report::new("batch");
+    let second = Report::new("verify");
+    std::thread::scope(|s| {
+        s.spawn(|| first.clone().input(Path::new("first.loadinfo.json")));
+        s.spawn(|| second.input(Path::new("second.loadinfo.json")));
+    });
+    {
+        let _phase = first.phase("prove", "first");
+    }
+    // the default handle records nothing
+    Report::default().output(Path::new("none.data"));
+
+    let inputs = |report: &Report| report.run.as_ref().unwrap().lock().unwrap().inputs.clone();
+    assert_eq!(inputs(&first), vec![PathBuf::from("first.loadinfo.json")]);
+    assert_eq!(inputs(&second), vec![PathBuf::from("second.loadinfo.json")]);
+    assert_eq!(first.run.as_ref().unwrap().lock().unwrap().phases.len(), 1);
+    assert!(second
+        .run
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .phases
+        .is_empty());
+}
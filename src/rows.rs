@@ -0,0 +1,214 @@
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::plonk::Advice;
+use halo2_proofs::plonk::Any;
+use halo2_proofs::plonk::Assigned;
+use halo2_proofs::plonk::Assignment;
+use halo2_proofs::plonk::Circuit;
+use halo2_proofs::plonk::Column;
+use halo2_proofs::plonk::ConstraintSystem;
+use halo2_proofs::plonk::Error;
+use halo2_proofs::plonk::Fixed;
+use halo2_proofs::plonk::FloorPlanner;
+use halo2_proofs::plonk::Instance;
+use halo2_proofs::plonk::Selector;
+use std::collections::BTreeMap;
+
+/// Rows used by a circuit, counted by synthesizing it once without a k bound.
+#[derive(Clone, Debug)]
+pub struct RowCount {
+    /// Rows assigned by the circuit, including instances.
+    pub rows: usize,
+    /// Rows the prover reserves for blinding at the end of the columns.
+    pub blinding_rows: usize,
+    /// Rows spanned by the regions of each name, in order of first use.
+    pub regions: Vec<(String, usize)>,
+}
+
+impl RowCount {
+    /// Minimal k whose usable rows hold the circuit.
+    pub fn min_k(&self) -> usize {
+        let total = self.rows + self.blinding_rows;
+        total.next_power_of_two().trailing_zeros() as usize
+    }
+}
+
+/// `Assignment` that only records the rows touched by the floor planner.
+struct RowCounter<'a, F: FieldExt> {
+    instances: &'a Vec<Vec<F>>,
+    rows: usize,
+    /// Name and row span of the region being assigned.
+    region: Option<(String, Option<(usize, usize)>)>,
+    region_rows: BTreeMap<String, (usize, usize)>,
+}
+
+impl<'a, F: FieldExt> RowCounter<'a, F> {
+    fn touch(&mut self, row: usize) {
+        self.rows = self.rows.max(row + 1);
+        if let Some((_, span)) = self.region.as_mut() {
+            *span = Some(match span {
+                Some((start, end)) => ((*start).min(row), (*end).max(row + 1)),
+                None => (row, row + 1),
+            });
+        }
+    }
+}
+
+impl<'a, F: FieldExt> Assignment<F> for RowCounter<'a, F> {
+    fn enter_region<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.region = Some((name_fn().into(), None));
+    }
+
+    fn exit_region(&mut self) {
+        if let Some((name, Some((start, end)))) = self.region.take() {
+            let order = self.region_rows.len();
+            let entry = self.region_rows.entry(name).or_insert((order, 0));
+            entry.1 += end - start;
+        }
+    }
+
+    fn enable_selector<A, AR>(&mut self, _: A, _: &Selector, row: usize) -> Result<(), Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn query_instance(&self, column: Column<Instance>, row: usize) -> Result<Option<F>, Error> {
+        Ok(self
+            .instances
+            .get(column.index())
+            .and_then(|column| column.get(row))
+            .cloned())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Advice>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Result<VR, Error>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Result<VR, Error>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn copy(&mut self, _: Column<Any>, _: usize, _: Column<Any>, _: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Fills the rest of a table column with its default value, which does not add rows.
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        _: usize,
+        _: Option<Assigned<F>>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
+
+/// Counts the rows of the circuit by running its floor planner once, instead of
+/// synthesizing it with the `MockProver` at each candidate k.
+pub fn count_rows<F: FieldExt, C: Circuit<F>>(
+    circuit: &C,
+    instances: &Vec<Vec<F>>,
+) -> Result<RowCount, Error> {
+    let mut cs = ConstraintSystem::default();
+    let config = C::configure(&mut cs);
+    let mut counter = RowCounter {
+        instances,
+        rows: instances.iter().map(|x| x.len()).max().unwrap_or(0),
+        region: None,
+        region_rows: BTreeMap::new(),
+    };
+    C::FloorPlanner::synthesize(&mut counter, circuit, config, cs.constants().clone())?;
+    let mut regions = counter
+        .region_rows
+        .into_iter()
+        .map(|(name, (order, rows))| (order, name, rows))
+        .collect::<Vec<_>>();
+    regions.sort();
+    Ok(RowCount {
+        rows: counter.rows,
+        // the last usable row is followed by the blinding rows
+        blinding_rows: cs.blinding_factors() + 1,
+        regions: regions
+            .into_iter()
+            .map(|(_, name, rows)| (name, rows))
+            .collect(),
+    })
+}
+
+#[test]
+fn count_simple_circuit_rows() {
+    use crate::samples::simple::SimpleCircuit;
+    use halo2_proofs::pairing::bn256::Fr;
+
+    let circuit = SimpleCircuit::<Fr> {
+        a: Fr::from(100u64),
+        b: Fr::from(200u64),
+    };
+    let count = count_rows(&circuit, &vec![vec![Fr::from(300u64)]]).unwrap();
+    // the range table of 1024 rows dominates the two rows of the main region
+    assert_eq!(count.rows, 1024);
+    assert!(count
+        .regions
+        .iter()
+        .any(|(name, rows)| name == "main" && *rows == 2));
+    // the MockProver accepts SimpleCircuit at k 11, see `test_simple_diff`
+    assert_eq!(count.min_k(), 11);
+}
+
+#[test]
+fn min_k_of_row_counts() {
+    let min_k = |rows, blinding_rows| {
+        RowCount {
+            rows,
+            blinding_rows,
+            regions: vec![],
+        }
+        .min_k()
+    };
+    assert_eq!(min_k(1, 0), 0);
+    assert_eq!(min_k(1020, 4), 10);
+    assert_eq!(min_k(1021, 4), 11);
+    assert_eq!(min_k(1 << 20, 0), 20);
+}
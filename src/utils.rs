@@ -1,8 +1,19 @@
+use crate::error::Error;
+use crate::error::Result;
+use ff::PrimeField;
 use sha2::Digest;
+use std::collections::HashMap;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
-pub fn construct_merkle_records(file: &PathBuf, data: &mut Vec<[u8; 32]>, depth: usize) {
+use std::sync::Mutex;
+use std::time::SystemTime;
+pub fn construct_merkle_records(
+    file: &PathBuf,
+    data: &mut Vec<[u8; 32]>,
+    depth: usize,
+) -> Result<()> {
     let len = data.len();
     let mut cursor = 0;
     let hasher = sha2::Sha256::new();
@@ -17,19 +28,19 @@ pub fn construct_merkle_records(file: &PathBuf, data: &mut Vec<[u8; 32]>, depth:
     }
 
     assert!(data.len() == len * 2 - 1);
-    let mut fd = std::fs::File::create(&file).unwrap();
+    let mut fd = std::fs::File::create(&file).map_err(Error::io(file))?;
     for v in data {
-        fd.write(v.as_slice())
-            .expect("writing merkle tree should not fail");
+        fd.write_all(v.as_slice()).map_err(Error::io(file))?;
     }
+    Ok(())
 }
 
-pub fn construct_merkle_proof(file: &PathBuf, index: usize, depth: usize) -> Vec<[u8; 32]> {
+pub fn construct_merkle_proof(file: &PathBuf, index: usize, depth: usize) -> Result<Vec<[u8; 32]>> {
     let mut data: Vec<[u8; 32]> = vec![];
-    let mut fd = std::fs::File::open(&file).unwrap();
+    let mut fd = std::fs::File::open(&file).map_err(Error::io(file))?;
     for _ in 0..2 * 2u32.pow(depth as u32) - 1 {
         let mut d = [0; 32];
-        fd.read(&mut d).expect("Read merkle data should not fail");
+        fd.read_exact(&mut d).map_err(Error::io(file))?;
         data.push(d);
     }
     let hasher = sha2::Sha256::new();
@@ -55,7 +66,122 @@ pub fn construct_merkle_proof(file: &PathBuf, index: usize, depth: usize) -> Vec
         comp = acc % 2;
         base = base + (2u32.pow((depth - d) as u32) as usize);
     }
-    proofs
+    Ok(proofs)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + usize::min(prev, usize::min(row[j], cur))
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns the candidate with the smallest edit distance to `name`.
+pub fn closest_name<'a, I: IntoIterator<Item = &'a str>>(
+    name: &str,
+    candidates: I,
+) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .min_by_key(|candidate| edit_distance(name, candidate))
+}
+
+/// Writes the file through a temporary file renamed over it, so that an interrupted
+/// write leaves the previous content in place.
+pub fn write_file_atomic(file: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp_name = file.as_os_str().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_file = PathBuf::from(tmp_name);
+    let mut fd = std::fs::File::create(&tmp_file).map_err(Error::io(&tmp_file))?;
+    fd.write_all(bytes).map_err(Error::io(&tmp_file))?;
+    fd.sync_all().map_err(Error::io(&tmp_file))?;
+    std::fs::rename(&tmp_file, file).map_err(Error::io(file))
+}
+
+pub fn file_size(file: &Path) -> Result<u64> {
+    Ok(std::fs::metadata(file).map_err(Error::io(file))?.len())
+}
+
+lazy_static::lazy_static! {
+    /// Digests computed by `file_digest`, with the size and modification time of the
+    /// file they were computed for.
+    static ref DIGESTS: Mutex<HashMap<PathBuf, (u64, Option<SystemTime>, String)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Hex of the sha256 digest of the file. Each file is hashed once per run, unless its
+/// size or modification time changes, as the multi GB circuit data of a batch is
+/// checked for every proof referencing it.
+pub fn file_digest(file: &Path) -> Result<String> {
+    memoized_digest(file, || sha256_file(file))
+}
+
+/// `file_digest` of a file from its memory mapped bytes, so that the pages read for the
+/// digest are the ones deserialized afterwards.
+pub fn mapped_file_digest(file: &Path, bytes: &[u8]) -> Result<String> {
+    memoized_digest(file, || {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(bytes);
+        Ok(hex::encode(hasher.finalize()))
+    })
+}
+
+fn memoized_digest(file: &Path, digest: impl FnOnce() -> Result<String>) -> Result<String> {
+    let metadata = std::fs::metadata(file).map_err(Error::io(file))?;
+    let stamp = (metadata.len(), metadata.modified().ok());
+    let key = file.canonicalize().map_err(Error::io(file))?;
+    if let Some((size, modified, digest)) = DIGESTS.lock().unwrap().get(&key) {
+        if (*size, *modified) == stamp {
+            return Ok(digest.clone());
+        }
+    }
+    let digest = digest()?;
+    DIGESTS
+        .lock()
+        .unwrap()
+        .insert(key, (stamp.0, stamp.1, digest.clone()));
+    Ok(digest)
+}
+
+fn sha256_file(file: &Path) -> Result<String> {
+    let mut fd = std::fs::File::open(file).map_err(Error::io(file))?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = fd.read(&mut buf).map_err(Error::io(file))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+pub fn field_to_hex<F: PrimeField>(f: &F) -> String {
+    hex::encode(f.to_repr().as_ref())
+}
+
+pub fn field_from_hex<F: PrimeField>(s: &str) -> Result<F> {
+    let invalid = || Error::InvalidArgument(format!("invalid field element {}", s));
+    let bytes = hex::decode(s).map_err(|_| invalid())?;
+    let mut repr = F::Repr::default();
+    if repr.as_ref().len() != bytes.len() {
+        return Err(invalid());
+    }
+    repr.as_mut().copy_from_slice(&bytes);
+    Option::from(F::from_repr(repr)).ok_or_else(invalid)
 }
 
 #[test]
@@ -65,7 +191,28 @@ fn merkle_test() {
         hashes.push([(i % 256) as u8; 32]);
     }
     let filepath = PathBuf::from("output/test_merkle.data");
-    construct_merkle_records(&filepath, &mut hashes, 10);
-    let proofs = construct_merkle_proof(&filepath, 12, 10);
+    construct_merkle_records(&filepath, &mut hashes, 10).unwrap();
+    let proofs = construct_merkle_proof(&filepath, 12, 10).unwrap();
     println!("proofs is {:?}", proofs);
 }
+
+#[test]
+fn field_hex_test() {
+    use halo2_proofs::pairing::bn256::Fr;
+
+    let f = Fr::from(300u64);
+    let hex = field_to_hex(&f);
+    // little endian repr of 0x012c
+    assert_eq!(hex, format!("2c01{}", "00".repeat(30)));
+    assert_eq!(field_from_hex::<Fr>(&hex).unwrap(), f);
+    let minus_one = -Fr::from(1u64);
+    assert_eq!(
+        field_from_hex::<Fr>(&field_to_hex(&minus_one)).unwrap(),
+        minus_one
+    );
+
+    // not hex, too short and not canonical
+    assert!(field_from_hex::<Fr>("zz").is_err());
+    assert!(field_from_hex::<Fr>("2c01").is_err());
+    assert!(field_from_hex::<Fr>(&"ff".repeat(32)).is_err());
+}